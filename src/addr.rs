@@ -4,9 +4,33 @@ use bit_field::BitField;
 pub struct VirtAddr(usize);
 
 impl VirtAddr {
-    pub fn new(addr: usize) -> VirtAddr {
+    pub const fn new(addr: usize) -> VirtAddr {
         VirtAddr(addr)
     }
+    /// Rounds the address up to a multiple of `align`, which must be a power of two.
+    ///
+    /// Panics if the result overflows.
+    pub fn align_up(&self, align: usize) -> Self {
+        assert!(align.is_power_of_two(), "alignment must be a power of two");
+        VirtAddr(self.0.checked_add(align - 1).expect("address overflow") & !(align - 1))
+    }
+    /// Rounds the address down to a multiple of `align`, which must be a power of two.
+    pub fn align_down(&self, align: usize) -> Self {
+        assert!(align.is_power_of_two(), "alignment must be a power of two");
+        VirtAddr(self.0 & !(align - 1))
+    }
+    /// Returns whether the address is a multiple of `align`, which must be a power of two.
+    pub fn is_aligned(&self, align: usize) -> bool {
+        self.align_down(align) == *self
+    }
+    /// Adds `offset` to the address, returning `None` on overflow.
+    pub fn checked_add(&self, offset: usize) -> Option<Self> {
+        self.0.checked_add(offset).map(VirtAddr)
+    }
+    /// Returns the distance from `base` to this address, or `None` if `base` lies above it.
+    pub fn offset_from(&self, base: VirtAddr) -> Option<usize> {
+        self.0.checked_sub(base.0)
+    }
     pub fn as_usize(&self) -> usize {
         self.0
     }
@@ -31,9 +55,41 @@ impl VirtAddr {
 pub struct PhysAddr(u32);
 
 impl PhysAddr {
-    pub fn new(addr: u32) -> PhysAddr {
+    pub const fn new(addr: u32) -> PhysAddr {
         PhysAddr(addr)
     }
+    /// Creates a PhysAddr, or returns `None` if `addr` lies above 4G.
+    ///
+    /// Sv32 page table entries can hold 34 bit physical addresses, but a `PhysAddr` only
+    /// covers the low 4G. Use this for addresses from firmware or device trees instead of
+    /// truncating them with `as u32`.
+    pub fn try_new(addr: u64) -> Option<PhysAddr> {
+        if addr >> 32 == 0 { Some(PhysAddr(addr as u32)) } else { None }
+    }
+    /// Rounds the address up to a multiple of `align`, which must be a power of two.
+    ///
+    /// Panics if the result overflows.
+    pub fn align_up(&self, align: u32) -> Self {
+        assert!(align.is_power_of_two(), "alignment must be a power of two");
+        PhysAddr(self.0.checked_add(align - 1).expect("address overflow") & !(align - 1))
+    }
+    /// Rounds the address down to a multiple of `align`, which must be a power of two.
+    pub fn align_down(&self, align: u32) -> Self {
+        assert!(align.is_power_of_two(), "alignment must be a power of two");
+        PhysAddr(self.0 & !(align - 1))
+    }
+    /// Returns whether the address is a multiple of `align`, which must be a power of two.
+    pub fn is_aligned(&self, align: u32) -> bool {
+        self.align_down(align) == *self
+    }
+    /// Adds `offset` to the address, returning `None` on overflow.
+    pub fn checked_add(&self, offset: u32) -> Option<Self> {
+        self.0.checked_add(offset).map(PhysAddr)
+    }
+    /// Returns the distance from `base` to this address, or `None` if `base` lies above it.
+    pub fn offset_from(&self, base: PhysAddr) -> Option<u32> {
+        self.0.checked_sub(base.0)
+    }
     pub fn as_u32(&self) -> u32 {
         self.0
     }
@@ -55,7 +111,7 @@ impl PhysAddr {
 pub struct Page(VirtAddr);
 
 impl Page {
-    pub fn of_addr(addr: VirtAddr) -> Self {
+    pub const fn of_addr(addr: VirtAddr) -> Self {
         Page(VirtAddr(addr.0 & 0xfffff000))
     }
     pub fn start_address(&self) -> VirtAddr {
@@ -77,13 +133,28 @@ impl Page {
         addr.set_bits(12..22, p1_index);
         Page::of_addr(VirtAddr::new(addr))
     }
+    /// Returns the pages overlapping the address range `[start, end)`.
+    ///
+    /// If `end` lies in the last page of the address space, the range can't be represented
+    /// with an exclusive end and stops before it; use `range_inclusive` instead.
+    pub fn range(start: VirtAddr, end: VirtAddr) -> PageRange {
+        let end = end.0.checked_add(PAGE_SIZE - 1).unwrap_or(end.0);
+        PageRange::new(Page::of_addr(start), Page::of_addr(VirtAddr::new(end)))
+    }
+    /// Returns the pages from `first` to `last`, both inclusive.
+    pub fn range_inclusive(first: Page, last: Page) -> PageRangeInclusive {
+        PageRangeInclusive::new(first, last)
+    }
+    fn add_pages(&self, pages: usize) -> Page {
+        Page::of_addr(VirtAddr::new(self.0.as_usize() + pages * PAGE_SIZE))
+    }
 }
 
 #[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord)]
 pub struct Frame(PhysAddr);
 
 impl Frame {
-    pub fn of_addr(addr: PhysAddr) -> Self {
+    pub const fn of_addr(addr: PhysAddr) -> Self {
         Frame(PhysAddr(addr.0 & 0xfffff000))
     }
     pub fn start_address(&self) -> PhysAddr {
@@ -98,4 +169,349 @@ impl Frame {
     pub fn number(&self) -> usize {
         self.0.page_number()
     }
-}
\ No newline at end of file
+    /// Returns the frames overlapping the physical address range `[start, end)`.
+    ///
+    /// If `end` lies in the last frame, the range stops before it.
+    pub fn range(start: PhysAddr, end: PhysAddr) -> FrameRange {
+        let end = end.0.checked_add(PAGE_SIZE as u32 - 1).unwrap_or(end.0);
+        FrameRange::new(Frame::of_addr(start), Frame::of_addr(PhysAddr::new(end)))
+    }
+    /// Returns the frames from `first` to `last`, both inclusive.
+    pub fn range_inclusive(first: Frame, last: Frame) -> FrameRangeInclusive {
+        FrameRangeInclusive::new(first, last)
+    }
+}
+
+/// Size of a page or frame in bytes.
+pub const PAGE_SIZE: usize = 1 << 12;
+
+/// A range of pages with exclusive upper bound.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct PageRange {
+    /// The start of the range, inclusive.
+    pub start: Page,
+    /// The end of the range, exclusive.
+    pub end: Page,
+}
+
+impl PageRange {
+    pub fn new(start: Page, end: Page) -> Self {
+        PageRange { start, end }
+    }
+    /// Returns whether the range contains no pages.
+    pub fn is_empty(&self) -> bool {
+        self.start >= self.end
+    }
+    /// Returns the number of pages in the range.
+    pub fn len(&self) -> usize {
+        if self.is_empty() { 0 } else { self.end.number() - self.start.number() }
+    }
+    /// Returns whether `page` lies in the range.
+    pub fn contains(&self, page: Page) -> bool {
+        self.start <= page && page < self.end
+    }
+    /// Returns the pages contained in both ranges, which may be empty.
+    pub fn intersection(&self, other: PageRange) -> PageRange {
+        PageRange::new(self.start.max(other.start), self.end.min(other.end))
+    }
+    /// Splits the range at every multiple of `size` bytes, e.g. `1 << 22` to step through it
+    /// one megapage at a time. `size` must be a power of two and at least `PAGE_SIZE`.
+    pub fn chunks(self, size: usize) -> PageChunks {
+        assert!(size.is_power_of_two() && size >= PAGE_SIZE, "invalid chunk size");
+        PageChunks { rest: self, pages: size / PAGE_SIZE }
+    }
+}
+
+impl Iterator for PageRange {
+    type Item = Page;
+
+    fn next(&mut self) -> Option<Page> {
+        if self.is_empty() {
+            return None;
+        }
+        let page = self.start;
+        self.start = page.add_pages(1);
+        Some(page)
+    }
+}
+
+/// An iterator over the aligned chunks of a `PageRange`, returned by `PageRange::chunks`.
+#[derive(Debug, Clone)]
+pub struct PageChunks {
+    rest: PageRange,
+    pages: usize,
+}
+
+impl Iterator for PageChunks {
+    type Item = PageRange;
+
+    fn next(&mut self) -> Option<PageRange> {
+        if self.rest.is_empty() {
+            return None;
+        }
+        let start = self.rest.start;
+        let len = (self.pages - start.number() % self.pages).min(self.rest.len());
+        let end = start.add_pages(len);
+        self.rest.start = end;
+        Some(PageRange::new(start, end))
+    }
+}
+
+/// A range of pages with inclusive upper bound, which can reach the last page of the address
+/// space.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct PageRangeInclusive {
+    /// The start of the range, inclusive.
+    pub start: Page,
+    /// The end of the range, inclusive.
+    pub end: Page,
+    exhausted: bool,
+}
+
+impl PageRangeInclusive {
+    pub fn new(start: Page, end: Page) -> Self {
+        PageRangeInclusive { start, end, exhausted: false }
+    }
+    /// Returns whether the range contains no pages.
+    pub fn is_empty(&self) -> bool {
+        self.exhausted || self.start > self.end
+    }
+    /// Returns the number of pages in the range.
+    pub fn len(&self) -> usize {
+        if self.is_empty() { 0 } else { self.end.number() - self.start.number() + 1 }
+    }
+    /// Returns whether `page` lies in the range.
+    pub fn contains(&self, page: Page) -> bool {
+        !self.is_empty() && self.start <= page && page <= self.end
+    }
+}
+
+impl Iterator for PageRangeInclusive {
+    type Item = Page;
+
+    fn next(&mut self) -> Option<Page> {
+        if self.is_empty() {
+            return None;
+        }
+        let page = self.start;
+        if page == self.end {
+            self.exhausted = true;
+        } else {
+            self.start = page.add_pages(1);
+        }
+        Some(page)
+    }
+}
+
+/// A range of frames with exclusive upper bound.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct FrameRange {
+    /// The start of the range, inclusive.
+    pub start: Frame,
+    /// The end of the range, exclusive.
+    pub end: Frame,
+}
+
+impl FrameRange {
+    pub fn new(start: Frame, end: Frame) -> Self {
+        FrameRange { start, end }
+    }
+    /// Returns whether the range contains no frames.
+    pub fn is_empty(&self) -> bool {
+        self.start >= self.end
+    }
+    /// Returns the number of frames in the range.
+    pub fn len(&self) -> usize {
+        if self.is_empty() { 0 } else { self.end.number() - self.start.number() }
+    }
+    /// Returns whether `frame` lies in the range.
+    pub fn contains(&self, frame: Frame) -> bool {
+        self.start <= frame && frame < self.end
+    }
+    /// Returns the frames contained in both ranges, which may be empty.
+    pub fn intersection(&self, other: FrameRange) -> FrameRange {
+        FrameRange::new(self.start.max(other.start), self.end.min(other.end))
+    }
+}
+
+impl Iterator for FrameRange {
+    type Item = Frame;
+
+    fn next(&mut self) -> Option<Frame> {
+        if self.is_empty() {
+            return None;
+        }
+        let frame = self.start;
+        self.start = Frame::of_addr(PhysAddr::new(frame.start_address().as_u32() + PAGE_SIZE as u32));
+        Some(frame)
+    }
+}
+
+/// A range of frames with inclusive upper bound, which can reach the last frame below 4G.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct FrameRangeInclusive {
+    /// The start of the range, inclusive.
+    pub start: Frame,
+    /// The end of the range, inclusive.
+    pub end: Frame,
+    exhausted: bool,
+}
+
+impl FrameRangeInclusive {
+    pub fn new(start: Frame, end: Frame) -> Self {
+        FrameRangeInclusive { start, end, exhausted: false }
+    }
+    fn empty() -> Self {
+        let frame = Frame::of_addr(PhysAddr::new(0));
+        FrameRangeInclusive { start: frame, end: frame, exhausted: true }
+    }
+    /// Returns whether the range contains no frames.
+    pub fn is_empty(&self) -> bool {
+        self.exhausted || self.start > self.end
+    }
+    /// Returns the number of frames in the range.
+    pub fn len(&self) -> usize {
+        if self.is_empty() { 0 } else { self.end.number() - self.start.number() + 1 }
+    }
+    /// Returns whether `frame` lies in the range.
+    pub fn contains(&self, frame: Frame) -> bool {
+        !self.is_empty() && self.start <= frame && frame <= self.end
+    }
+}
+
+impl Iterator for FrameRangeInclusive {
+    type Item = Frame;
+
+    fn next(&mut self) -> Option<Frame> {
+        if self.is_empty() {
+            return None;
+        }
+        let frame = self.start;
+        if frame == self.end {
+            self.exhausted = true;
+        } else {
+            self.start = Frame::of_addr(PhysAddr::new(frame.start_address().as_u32() + PAGE_SIZE as u32));
+        }
+        Some(frame)
+    }
+}
+
+/// A contiguous range of physical memory.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct PhysRange {
+    pub start: PhysAddr,
+    /// Length of the range in bytes.
+    pub len: usize,
+}
+
+impl PhysRange {
+    pub fn new(start: PhysAddr, len: usize) -> Self {
+        PhysRange { start, len }
+    }
+    /// Returns the first address after the range, or `None` if the range ends at 4G.
+    pub fn end(&self) -> Option<PhysAddr> {
+        let end = self.end_u64();
+        if end >> 32 == 0 { Some(PhysAddr::new(end as u32)) } else { None }
+    }
+    /// Returns the end of the range as a 64 bit address, which may be 4G.
+    fn end_u64(&self) -> u64 {
+        self.start.0 as u64 + self.len as u64
+    }
+    /// Returns the frames overlapping the range, e.g. to map a device MMIO window.
+    pub fn frames(&self) -> FrameRangeInclusive {
+        if self.len == 0 {
+            return FrameRangeInclusive::empty();
+        }
+        let last = PhysAddr::new((self.end_u64() - 1) as u32);
+        Frame::range_inclusive(Frame::of_addr(self.start), Frame::of_addr(last))
+    }
+    /// Returns the frames lying completely inside the range, e.g. the usable frames of a
+    /// memory region reported by firmware.
+    pub fn contained_frames(&self) -> FrameRangeInclusive {
+        let first = (self.start.0 as u64 + PAGE_SIZE as u64 - 1) / PAGE_SIZE as u64;
+        let end = self.end_u64() / PAGE_SIZE as u64;
+        if first >= end {
+            return FrameRangeInclusive::empty();
+        }
+        let frame = |number: u64| Frame::of_addr(PhysAddr::new((number * PAGE_SIZE as u64) as u32));
+        Frame::range_inclusive(frame(first), frame(end - 1))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn page(addr: usize) -> Page {
+        Page::of_addr(VirtAddr::new(addr))
+    }
+
+    #[test]
+    fn virt_addr_indices() {
+        // (address, p2 index, p1 index, page offset)
+        let vectors = [
+            (0x0000_0000, 0, 0, 0),
+            (0x0040_1234, 1, 1, 0x234),
+            (0x0080_3abc, 2, 3, 0xabc),
+            (0x8020_5fff, 0x200, 0x205, 0xfff),
+            (0xffff_ffff, 0x3ff, 0x3ff, 0xfff),
+        ];
+        for &(addr, p2, p1, offset) in vectors.iter() {
+            let addr = VirtAddr::new(addr);
+            assert_eq!((addr.p2_index(), addr.p1_index(), addr.page_offset()), (p2, p1, offset));
+            assert_eq!(Page::from_page_table_indices(p2, p1), Page::of_addr(addr));
+        }
+    }
+
+    #[test]
+    fn phys_addr_try_new() {
+        assert_eq!(PhysAddr::try_new(0x8020_0000), Some(PhysAddr::new(0x8020_0000)));
+        assert_eq!(PhysAddr::try_new(0xffff_ffff), Some(PhysAddr::new(0xffff_ffff)));
+        assert_eq!(PhysAddr::try_new(1 << 32), None);
+        assert_eq!(PhysAddr::try_new(0x3_8020_0000), None);
+    }
+
+    #[test]
+    fn page_range() {
+        let range = Page::range(VirtAddr::new(0x1800), VirtAddr::new(0x4001));
+        assert_eq!(range, PageRange::new(page(0x1000), page(0x5000)));
+        assert_eq!(range.len(), 4);
+        assert!(range.contains(page(0x4000)));
+        assert!(!range.contains(page(0x5000)));
+        assert_eq!(range.intersection(PageRange::new(page(0x3000), page(0x9000))).len(), 2);
+        assert!(range.intersection(PageRange::new(page(0x8000), page(0x9000))).is_empty());
+
+        let mut pages = range;
+        assert_eq!(pages.next(), Some(page(0x1000)));
+        assert_eq!(pages.last(), Some(page(0x4000)));
+
+        let empty = PageRange::new(page(0x5000), page(0x1000));
+        assert!(empty.is_empty());
+        assert_eq!(empty.len(), 0);
+    }
+
+    #[test]
+    fn page_range_inclusive_reaches_last_page() {
+        let last = page(0xffff_f000);
+        let mut range = Page::range_inclusive(page(0xffff_e000), last);
+        assert_eq!(range.len(), 2);
+        assert_eq!(range.next(), Some(page(0xffff_e000)));
+        assert_eq!(range.next(), Some(last));
+        assert_eq!(range.next(), None);
+        assert!(range.is_empty());
+    }
+
+    #[test]
+    fn page_chunks() {
+        let mut chunks = PageRange::new(page(0x003f_e000), page(0x0080_3000)).chunks(1 << 22);
+        assert_eq!(chunks.next(), Some(PageRange::new(page(0x003f_e000), page(0x0040_0000))));
+        assert_eq!(chunks.next(), Some(PageRange::new(page(0x0040_0000), page(0x0080_0000))));
+        assert_eq!(chunks.next(), Some(PageRange::new(page(0x0080_0000), page(0x0080_3000))));
+        assert_eq!(chunks.next(), None);
+
+        let mut chunks = PageRange::new(page(0x1000), page(0x2000)).chunks(1 << 22);
+        assert_eq!(chunks.next(), Some(PageRange::new(page(0x1000), page(0x2000))));
+        assert_eq!(chunks.next(), None);
+        assert_eq!(PageRange::new(page(0x2000), page(0x2000)).chunks(PAGE_SIZE).next(), None);
+    }
+}
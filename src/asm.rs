@@ -27,10 +27,33 @@ use addr::VirtAddr;
 
 #[inline(always)]
 pub fn sfence_vma_all() {
+    #[cfg(feature = "fault-injection")]
+    ::inject::count_sfence();
     unsafe{ asm!("sfence.vma" :::: "volatile"); }
 }
 
+/// Flushes the translation of `addr` in the address space `asid`.
+///
+/// Global mappings are not affected, see `sfence_vma_addr`.
 #[inline(always)]
 pub fn sfence_vma(asid: usize, addr: VirtAddr) {
-    unsafe{ asm!("sfence.vma $0, $1" :: "r"(asid), "r"(addr.as_usize()) :: "volatile"); }
+    #[cfg(feature = "fault-injection")]
+    ::inject::count_sfence();
+    unsafe{ asm!("sfence.vma $0, $1" :: "r"(addr.as_usize()), "r"(asid) :: "volatile"); }
+}
+
+/// Flushes all translations of the address space `asid`, except global mappings.
+#[inline(always)]
+pub fn sfence_vma_asid(asid: usize) {
+    #[cfg(feature = "fault-injection")]
+    ::inject::count_sfence();
+    unsafe{ asm!("sfence.vma zero, $0" :: "r"(asid) :: "volatile"); }
+}
+
+/// Flushes the translation of `addr` in all address spaces, including global mappings.
+#[inline(always)]
+pub fn sfence_vma_addr(addr: VirtAddr) {
+    #[cfg(feature = "fault-injection")]
+    ::inject::count_sfence();
+    unsafe{ asm!("sfence.vma $0, zero" :: "r"(addr.as_usize()) :: "volatile"); }
 }
\ No newline at end of file
@@ -0,0 +1,101 @@
+//! Privileged state handoff
+//!
+//! Bootloaders and kexec-like flows need to save the supervisor state they were started with
+//! and reinstate it later, e.g. before handing control to the next stage. `BootInfo` collects
+//! what an early boot stage knows about the machine in one place.
+
+use addr::*;
+use asm::sfence_vma_all;
+use register::{medeleg, mhartid, mideleg, satp, sie, sstatus, stvec};
+
+/// Snapshot of the privileged state relevant for a handoff.
+#[derive(Clone, Copy, Debug)]
+pub struct HandoffState {
+    pub satp: satp::Satp,
+    pub sstatus: sstatus::Sstatus,
+    pub sie: sie::Sie,
+    pub stvec: stvec::Stvec,
+    /// `(medeleg, mideleg)`, only captured in M-mode.
+    pub delegation: Option<(usize, usize)>,
+}
+
+impl HandoffState {
+    /// Captures the supervisor state.
+    pub fn capture() -> Self {
+        HandoffState {
+            satp: satp::read(),
+            sstatus: sstatus::read(),
+            sie: sie::read(),
+            stvec: stvec::read(),
+            delegation: None,
+        }
+    }
+
+    /// Captures the supervisor state and the trap delegation registers.
+    ///
+    /// Must be called from M-mode.
+    pub fn capture_machine() -> Self {
+        HandoffState {
+            delegation: Some((medeleg::read(), mideleg::read())),
+            ..Self::capture()
+        }
+    }
+
+    /// Reinstates the captured state.
+    ///
+    /// The delegation registers are only written if they were captured. `sstatus` is restored
+    /// last, so interrupts are not enabled before the rest of the state is in place.
+    pub unsafe fn restore(&self) {
+        sstatus::clear_sie();
+        if let Some((medeleg, mideleg)) = self.delegation {
+            medeleg::write(medeleg);
+            mideleg::write(mideleg);
+        }
+        stvec::write(self.stvec.address(), self.stvec.trap_mode());
+        sie::write(self.sie);
+        satp::write(self.satp);
+        sfence_vma_all();
+        sstatus::write(self.sstatus);
+    }
+}
+
+/// What a kernel is told about the machine at boot.
+#[derive(Clone, Copy, Debug)]
+pub struct BootInfo<'a> {
+    /// The id of the hart running the boot code.
+    pub hartid: usize,
+    /// The physical address of the flattened device tree, as passed in `a1`.
+    ///
+    /// Kept as a `usize` so it is never truncated; use `PhysAddr::try_new` to convert it.
+    pub dtb: usize,
+    /// The usable physical memory regions, empty if not known yet, e.g. before the device tree
+    /// was parsed.
+    pub memory: &'a [PhysRange],
+    /// The value of `satp` when the kernel was entered; its mode tells whether paging is on.
+    pub satp: satp::Satp,
+    /// The physical address of a console device, e.g. a UART for early output.
+    pub console: Option<PhysAddr>,
+}
+
+impl<'a> BootInfo<'a> {
+    /// Creates the boot information of a kernel started by SBI firmware in S-mode, with the
+    /// hart id and device tree address passed in `a0` and `a1`.
+    pub fn from_sbi(hartid: usize, dtb: usize) -> Self {
+        BootInfo {
+            hartid,
+            dtb,
+            memory: &[],
+            satp: satp::read(),
+            console: None,
+        }
+    }
+
+    /// Creates the boot information of a kernel started in M-mode, which reads the hart id
+    /// itself. `dtb` is the device tree address passed by the previous stage in `a1`.
+    ///
+    /// Must be called from M-mode.
+    pub fn from_machine(dtb: usize) -> Self {
+        BootInfo { hartid: mhartid::read(), ..Self::from_sbi(0, dtb) }
+    }
+}
+
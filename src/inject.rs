@@ -0,0 +1,72 @@
+//! Fault injection and instrumentation hooks
+//!
+//! Only available with the `fault-injection` feature. Kernels can use these hooks to test
+//! their error handling paths and to check how many TLB flushes an operation issues.
+
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+static SFENCE_COUNT: AtomicUsize = AtomicUsize::new(0);
+
+pub(crate) fn count_sfence() {
+    SFENCE_COUNT.fetch_add(1, Ordering::Relaxed);
+}
+
+/// Returns the number of `sfence.vma` instructions issued through `asm` since the last reset.
+pub fn sfence_count() -> usize {
+    SFENCE_COUNT.load(Ordering::Relaxed)
+}
+
+/// Resets the `sfence.vma` counter to zero.
+pub fn reset_sfence_count() {
+    SFENCE_COUNT.store(0, Ordering::Relaxed);
+}
+
+#[cfg(feature = "paging")]
+pub use self::alloc::*;
+
+#[cfg(feature = "paging")]
+mod alloc {
+    use addr::Frame;
+    use paging::{FrameAllocator, FrameDeallocator};
+
+    /// A frame allocator wrapper that fails the `n`th allocation, counting from 1.
+    ///
+    /// Use it to force `FrameAllocationFailed` at a specific point of a mapping operation.
+    pub struct FailNthAllocator<A> {
+        inner: A,
+        fail_at: usize,
+        count: usize,
+    }
+
+    impl<A: FrameAllocator> FailNthAllocator<A> {
+        pub fn new(inner: A, n: usize) -> Self {
+            FailNthAllocator { inner, fail_at: n, count: 0 }
+        }
+
+        /// Returns the number of allocations attempted so far, including the failed one.
+        pub fn allocations(&self) -> usize {
+            self.count
+        }
+
+        /// Returns the wrapped allocator.
+        pub fn into_inner(self) -> A {
+            self.inner
+        }
+    }
+
+    impl<A: FrameAllocator> FrameAllocator for FailNthAllocator<A> {
+        fn alloc(&mut self) -> Option<Frame> {
+            self.count += 1;
+            if self.count == self.fail_at {
+                return None;
+            }
+            self.inner.alloc()
+        }
+    }
+
+    impl<A: FrameDeallocator> FrameDeallocator for FailNthAllocator<A> {
+        fn dealloc(&mut self, frame: Frame) {
+            self.inner.dealloc(frame)
+        }
+    }
+}
@@ -0,0 +1,53 @@
+//! Reading the instruction that caused a trap
+//!
+//! Emulating an instruction or stepping over it requires its encoding. The instruction may be
+//! compressed, lie in a user page or in an execute-only page, and a 32 bit instruction may
+//! even cross a page boundary, so a plain load from `sepc` is not enough.
+
+use register::sstatus;
+
+/// Returns whether the instruction starting with the 16 bit parcel `low` is a compressed one.
+#[inline]
+pub fn is_compressed(low: u16) -> bool {
+    low & 0b11 != 0b11
+}
+
+/// Returns the length in bytes of the instruction starting with the 16 bit parcel `low`.
+#[inline]
+pub fn len(low: u16) -> usize {
+    if is_compressed(low) { 2 } else { 4 }
+}
+
+/// Reads the instruction at `pc` from S-mode.
+///
+/// `sstatus.SUM` and `sstatus.MXR` are set during the read, so user pages and execute-only
+/// pages can be read, and restored afterwards. The instruction is read in 16 bit parcels, as
+/// `pc` may only be 2 byte aligned. A compressed instruction is returned zero extended.
+///
+/// # Safety
+///
+/// `pc` must be mapped with `EXECUTABLE` in the active page table; a fault while reading it
+/// is taken by the kernel itself.
+pub unsafe fn fetch(pc: usize) -> u32 {
+    sstatus::with_sum(|| sstatus::with_mxr(|| {
+        let low = ::core::ptr::read_volatile(pc as *const u16);
+        if is_compressed(low) {
+            low as u32
+        } else {
+            let high = ::core::ptr::read_volatile((pc + 2) as *const u16);
+            low as u32 | (high as u32) << 16
+        }
+    }))
+}
+
+/// Reads the instruction that trapped into S-mode.
+///
+/// `sepc` is the value of the `sepc` register saved in the trap frame on trap entry. The live
+/// register can't be used, as a nested trap in the handler overwrites it.
+///
+/// # Safety
+///
+/// See `fetch`.
+pub unsafe fn fetch_faulting_instruction(sepc: usize) -> u32 {
+    fetch(sepc)
+}
@@ -0,0 +1,92 @@
+//! Jumping to a new kernel image
+//!
+//! Turning paging off is the subtle part of a kexec: the instruction after the `satp` write is
+//! fetched from the physical address following it, so the code doing the switch has to run
+//! from an identity mapped page. This module provides a one page trampoline for that.
+
+use addr::*;
+use paging::{FrameAllocator, MapToError, Mapper, PageTableFlags};
+use register::sstatus;
+
+#[cfg(any(target_arch = "riscv32", target_arch = "riscv64"))]
+global_asm!("
+    .section .text.kexec_trampoline, \"ax\"
+    .balign 4096
+    .global __kexec_trampoline
+__kexec_trampoline:
+    csrw satp, zero
+    sfence.vma
+    jr a2
+");
+
+extern "C" {
+    fn __kexec_trampoline();
+}
+
+/// An error returned by `Trampoline::prepare`.
+#[derive(Debug)]
+pub enum TrampolineError {
+    /// The trampoline code is not mapped in the active page table.
+    NotMapped,
+    /// Identity mapping the trampoline page failed.
+    MapFailed(MapToError),
+}
+
+/// The identity mapped trampoline page, as returned by `Trampoline::prepare`.
+#[derive(Debug, Copy, Clone)]
+pub struct Trampoline {
+    frame: Frame,
+}
+
+impl Trampoline {
+    /// Identity maps the page holding the trampoline code in the active page table.
+    ///
+    /// Nothing is changed if the page is already identity mapped. `PageAlreadyMapped` is
+    /// returned if the virtual page with the trampoline's physical address is in use otherwise,
+    /// and `NotMapped` if the trampoline code itself isn't mapped by `mapper`.
+    pub fn prepare<M, A>(mapper: &mut M, allocator: &mut A) -> Result<Self, TrampolineError>
+        where M: Mapper, A: FrameAllocator,
+    {
+        type F = PageTableFlags;
+        let code = Page::of_addr(VirtAddr::new(__kexec_trampoline as usize));
+        let frame = mapper.translate_page(code).ok_or(TrampolineError::NotMapped)?;
+        let page = Page::of_addr(VirtAddr::new(frame.start_address().as_u32() as usize));
+        match mapper.translate_page(page) {
+            Some(f) if f == frame => {}
+            Some(_) => return Err(TrampolineError::MapFailed(MapToError::PageAlreadyMapped)),
+            None => mapper.identity_map(frame, F::VALID | F::READABLE | F::EXECUTABLE, allocator)
+                .map_err(TrampolineError::MapFailed)?.flush(),
+        }
+        Ok(Trampoline { frame })
+    }
+
+    /// Returns the physical (and virtual) address of the trampoline.
+    pub fn addr(&self) -> PhysAddr {
+        self.frame.start_address()
+    }
+}
+
+/// Disables interrupts and paging and jumps to the image at physical address `entry`.
+///
+/// The image is entered in S-mode with `a0 = hartid` and `a1 = dtb`, following the Linux boot
+/// convention. The hart id has to be passed in, as `mhartid` can't be read from S-mode.
+///
+/// # Safety
+///
+/// The image must be loaded completely at `entry`, and nothing may be left that relies on the
+/// current address space, e.g. other harts still running the old kernel.
+pub unsafe fn jump(trampoline: Trampoline, entry: PhysAddr, dtb: PhysAddr, hartid: usize) -> ! {
+    sstatus::clear_sie();
+    match () {
+        #[cfg(any(target_arch = "riscv32", target_arch = "riscv64"))]
+        () => asm!("jr $3"
+            :: "{x10}"(hartid), "{x11}"(dtb.as_u32() as usize), "{x12}"(entry.as_u32() as usize),
+               "r"(trampoline.addr().as_u32() as usize)
+            :: "volatile"),
+        #[cfg(not(any(target_arch = "riscv32", target_arch = "riscv64")))]
+        () => {
+            let _ = (trampoline, entry, dtb, hartid);
+        }
+    }
+    unreachable!()
+}
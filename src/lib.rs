@@ -5,19 +5,32 @@
 //! - Access to core registers like mstatus or mcause.
 //! - Interrupt manipulation mechanisms.
 //! - Safe wrappers around assembly instructions like `mret`.
+//! - Saving and restoring privileged state across a firmware to kernel handoff.
+//! - Jumping to a new kernel image with paging turned off.
+//! - Reading the instruction that caused a trap, for emulation.
+//! - Sv32 page table management, behind the default `paging` feature.
 
 #![no_std]
 //#![deny(warnings)]
 #![feature(asm)]
 #![feature(const_fn)]
+#![feature(global_asm)]
 
 extern crate bare_metal;
+#[cfg(feature = "paging")]
 #[macro_use]
 extern crate bitflags;
 extern crate bit_field;
 
 pub mod asm;
+pub mod handoff;
+#[cfg(feature = "fault-injection")]
+pub mod inject;
+pub mod instruction;
 pub mod interrupt;
+#[cfg(feature = "paging")]
+pub mod kexec;
 pub mod register;
 pub mod addr;
+#[cfg(feature = "paging")]
 pub mod paging;
@@ -0,0 +1,115 @@
+use super::mapped::*;
+use super::page_table::*;
+use super::recursive::*;
+use addr::*;
+
+/// An error returned when validating a page table built outside of the kernel.
+#[derive(Debug)]
+pub enum AdoptError {
+    /// The table has no recursive entry or no `VALID | READABLE | WRITABLE` self entry.
+    NoRecursiveEntry,
+    /// The table has more than one entry of a kind that points to itself.
+    DuplicateRecursiveEntry,
+    /// The entry mapping the address uses a reserved encoding: `WRITABLE` without `READABLE`,
+    /// a leaf p1 entry without `READABLE`, `WRITABLE` and `EXECUTABLE`, or `USER`, `ACCESSED`
+    /// or `DIRTY` on a non-leaf entry.
+    ReservedEncoding(VirtAddr),
+    /// The 4M page at the address is not aligned to 4M physically.
+    MisalignedMegapage(VirtAddr),
+    /// The address lies in the kernel range but is mapped with `USER`.
+    UserKernelMapping(VirtAddr),
+    /// The table is valid, but not the active one.
+    NotActive,
+}
+
+fn is_leaf(flags: PageTableFlags) -> bool {
+    flags.intersects(PageTableFlags::READABLE | PageTableFlags::WRITABLE | PageTableFlags::EXECUTABLE)
+}
+
+fn check_leaf(flags: PageTableFlags, addr: VirtAddr, kernel: PageRange) -> Result<(), AdoptError> {
+    type F = PageTableFlags;
+    if flags.contains(F::WRITABLE) && !flags.contains(F::READABLE) && !cfg!(feature = "cfi") {
+        return Err(AdoptError::ReservedEncoding(addr));
+    }
+    let page = Page::of_addr(addr);
+    if flags.contains(F::USER) && page >= kernel.start && page < kernel.end {
+        return Err(AdoptError::UserKernelMapping(addr));
+    }
+    Ok(())
+}
+
+/// Validates the page table rooted at `root`, whose frames are accessed through `physmap`.
+///
+/// Checks all valid entries for reserved encodings and misaligned 4M pages, and checks that
+/// no page in `kernel` is mapped with `USER`. The recursive entries are reported as
+/// `(recursive_index, rw_index)`, if the table has them. Entries pointing to `root` are not
+/// followed.
+pub fn validate_table<P: PhysToVirt>(root: Frame, physmap: &P, kernel: PageRange) -> Result<Option<(usize, usize)>, AdoptError> {
+    type F = PageTableFlags;
+    let p2 = unsafe { &*physmap.phys_to_virt(root) };
+    let (mut recursive, mut rw) = (None, None);
+    for i in 0..ENTRY_COUNT {
+        let entry = &p2[i];
+        let flags = entry.flags();
+        let addr = VirtAddr::new(i << 22);
+        if !flags.contains(F::VALID) {
+            continue;
+        }
+        if entry.frame() == root {
+            let slot = if is_leaf(flags) { &mut rw } else { &mut recursive };
+            if slot.is_some() {
+                return Err(AdoptError::DuplicateRecursiveEntry);
+            }
+            *slot = Some(i);
+            continue;
+        }
+        if is_leaf(flags) {
+            check_leaf(flags, addr, kernel)?;
+            if entry.frame().p1_index() != 0 {
+                return Err(AdoptError::MisalignedMegapage(addr));
+            }
+            continue;
+        }
+        if flags.intersects(F::USER | F::ACCESSED | F::DIRTY) {
+            return Err(AdoptError::ReservedEncoding(addr));
+        }
+        let p1 = unsafe { &*physmap.phys_to_virt(entry.frame()) };
+        for j in 0..ENTRY_COUNT {
+            let flags = p1[j].flags();
+            let addr = VirtAddr::new(i << 22 | j << 12);
+            if !flags.contains(F::VALID) {
+                continue;
+            }
+            if !is_leaf(flags) {
+                return Err(AdoptError::ReservedEncoding(addr));
+            }
+            check_leaf(flags, addr, kernel)?;
+        }
+    }
+    match (recursive, rw) {
+        (Some(recursive), Some(rw)) => Ok(Some((recursive, rw))),
+        (None, None) => Ok(None),
+        _ => Err(AdoptError::NoRecursiveEntry),
+    }
+}
+
+impl<'a> RecursivePageTable<'a> {
+    /// Validates a page table built elsewhere, e.g. by a bootloader, and takes ownership of it.
+    ///
+    /// `physmap` must give access to all frames of the table. See `validate_table` for the
+    /// checks; in addition the table must be recursively mapped and active.
+    ///
+    /// This function is unsafe because the table must not be accessed otherwise as long as the
+    /// returned RecursivePageTable lives.
+    pub unsafe fn adopt<P: PhysToVirt>(root: Frame, physmap: &P, kernel: PageRange) -> Result<Self, AdoptError> {
+        use register::satp;
+        let (recursive_index, rw_index) = validate_table(root, physmap, kernel)?
+            .ok_or(AdoptError::NoRecursiveEntry)?;
+        if satp::read().frame() != root {
+            return Err(AdoptError::NotActive);
+        }
+        let addr = Page::from_page_table_indices(recursive_index, rw_index).start_address();
+        RecursivePageTable::new(&mut *(addr.as_usize() as *mut PageTable))
+            .map_err(|_| AdoptError::NoRecursiveEntry)
+    }
+}
@@ -0,0 +1,115 @@
+use super::frame_alloc::*;
+use super::page_table::*;
+use super::phys::*;
+use super::recursive::*;
+use super::walk::*;
+use addr::*;
+
+fn read_entry<P: PhysRead>(phys: &mut P, table: Frame, index: usize) -> u32 {
+    let mut buf = [0u8; 4];
+    phys.read_phys(PhysAddr::new(table.start_address().as_u32() + 4 * index as u32), &mut buf);
+    buf[0] as u32 | (buf[1] as u32) << 8 | (buf[2] as u32) << 16 | (buf[3] as u32) << 24
+}
+
+fn write_entry<P: PhysWrite>(phys: &mut P, table: Frame, index: usize, entry: PageTableEntry) {
+    let entry = entry.bits();
+    let buf = [entry as u8, (entry >> 8) as u8, (entry >> 16) as u8, (entry >> 24) as u8];
+    phys.write_phys(PhysAddr::new(table.start_address().as_u32() + 4 * index as u32), &buf);
+}
+
+/// Returns the p1 table of the new table for `p2_index`, allocating and zeroing it if needed.
+fn p1_table<P, A>(phys: &mut P, root: Frame, p2_index: usize, allocator: &mut A) -> Result<Frame, MapToError>
+    where P: PhysRead + PhysWrite, A: FrameAllocator,
+{
+    type F = PageTableFlags;
+    let p2_entry = read_entry(phys, root, p2_index);
+    let p2_flags = F::from_bits_truncate(p2_entry);
+    if !p2_flags.contains(F::VALID) {
+        let p1_frame = allocator.alloc().ok_or(MapToError::FrameAllocationFailed)?;
+        phys.clear_frame(p1_frame);
+        write_entry(phys, root, p2_index, PageTableEntry::new(p1_frame, F::VALID));
+        Ok(p1_frame)
+    } else if p2_flags.intersects(F::READABLE | F::WRITABLE | F::EXECUTABLE) {
+        Err(MapToError::ParentEntryHugePage)
+    } else {
+        Ok(Frame::of_addr(PhysAddr::new((p2_entry << 2) & 0xfffff000)))
+    }
+}
+
+/// Copies all `USER` mappings of `src` into the page table rooted at `new_root`.
+///
+/// The copies map the same frames with the same flags, 4M mappings are copied as 4M mappings.
+/// A reference is added to every shared frame owned by `allocator`, see
+/// `SharedFrameAllocator::owns`; other frames, e.g. MMIO or the shared zero page, are shared
+/// without reference counting. Missing p1 tables are allocated from `allocator` and zeroed.
+/// The new tables are accessed through `phys`, so they don't need to be mapped. `new_root`
+/// must be zeroed or only contain mappings that don't overlap the user mappings of `src`,
+/// e.g. the shared kernel mappings.
+///
+/// Non-present entries, i.e. swap, reserved and guard entries, don't have a `USER` flag. They
+/// are read with `Translate::leaf_entry` and copied wherever the entry of the new table is
+/// still unused; the caller must account for copied swap payloads, e.g. by adding a
+/// reference to the swap slot.
+///
+/// Returns `ParentEntryHugePage` if a user page lies in a 4M mapping of the new table and
+/// `PageAlreadyMapped` if a 4M user mapping overlaps an entry of the new table.
+pub fn clone_user_mappings<T, P, A>(src: &T, new_root: Frame, phys: &mut P, allocator: &mut A) -> Result<(), MapToError>
+    where T: Translate, P: PhysRead + PhysWrite, A: SharedFrameAllocator,
+{
+    type F = PageTableFlags;
+    for (page, frame, flags, size) in Mappings::new(src) {
+        if !flags.contains(F::USER) {
+            continue;
+        }
+        if size == PageSize::Size4MiB {
+            if read_entry(phys, new_root, page.p2_index()) & F::VALID.bits() != 0 {
+                return Err(MapToError::PageAlreadyMapped);
+            }
+            write_entry(phys, new_root, page.p2_index(), PageTableEntry::new(frame, flags));
+            let start = frame.start_address().as_u32();
+            for i in 0..ENTRY_COUNT as u32 {
+                let frame = Frame::of_addr(PhysAddr::new(start + i * PAGE_SIZE as u32));
+                if allocator.owns(frame) {
+                    allocator.inc_ref(frame);
+                }
+            }
+            continue;
+        }
+        let p1_frame = p1_table(phys, new_root, page.p2_index(), allocator)?;
+        write_entry(phys, p1_frame, page.p1_index(), PageTableEntry::new(frame, flags));
+        if allocator.owns(frame) {
+            allocator.inc_ref(frame);
+        }
+    }
+    for p2_index in 0..ENTRY_COUNT {
+        let region = VirtAddr::new(p2_index << 22);
+        if !src.region_mapped(region) {
+            continue;
+        }
+        for p1_index in 0..ENTRY_COUNT {
+            let page = Page::of_addr(VirtAddr::new(region.as_usize() + p1_index * PAGE_SIZE));
+            let entry = match src.leaf_entry(page) {
+                Some(entry) => entry,
+                None => break,
+            };
+            if entry.is_unused() || entry.flags().contains(F::VALID) {
+                continue;
+            }
+            let p1_frame = p1_table(phys, new_root, p2_index, allocator)?;
+            if read_entry(phys, p1_frame, p1_index) == 0 {
+                write_entry(phys, p1_frame, p1_index, entry);
+            }
+        }
+    }
+    Ok(())
+}
+
+impl<'a> RecursivePageTable<'a> {
+    /// Copies all `USER` mappings into the page table rooted at `new_root`, e.g. for `fork`.
+    /// See `clone_user_mappings`.
+    pub fn clone_into<P, A>(&self, new_root: Frame, phys: &mut P, allocator: &mut A) -> Result<(), MapToError>
+        where P: PhysRead + PhysWrite, A: SharedFrameAllocator,
+    {
+        clone_user_mappings(self, new_root, phys, allocator)
+    }
+}
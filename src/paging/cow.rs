@@ -0,0 +1,72 @@
+use super::frame_alloc::*;
+use super::page_table::*;
+use super::phys::*;
+use super::recursive::*;
+use addr::*;
+
+/// An error returned by `resolve_cow_fault`.
+#[derive(Debug)]
+pub enum CowError {
+    /// The page is not mapped by a 4K page.
+    PageNotMapped,
+    /// The page is not marked copy-on-write.
+    NotCopyOnWrite,
+    /// No frame could be allocated for the private copy.
+    FrameAllocationFailed,
+}
+
+/// Makes every writable 4K page in `pages` a read-only copy-on-write page.
+///
+/// Pages that are only temporarily write-protected, i.e. in `SoftwareState::WriteProtected`
+/// or `SoftwareState::SoftDirtyProtected`, are writable as well and are converted too, so
+/// `write_enable_all` or `resolve_soft_dirty_fault` can't make a shared frame writable later.
+/// The pages are put into `SoftwareState::CopyOnWrite`, which neither of them touches. 4M
+/// mappings are left untouched.
+///
+/// The frames are shared once the mappings are copied, e.g. by `clone_user_mappings`, which
+/// adds a reference to every owned frame regardless of its flags.
+pub fn mark_cow<M>(mapper: &mut M, pages: PageRange) -> MapperFlushAll where M: Mapper + Translate {
+    type F = PageTableFlags;
+    for page in pages {
+        if let TranslateResult::Mapped { flags, size: PageSize::Size4KiB, .. } = mapper.translate(page.start_address()) {
+            let writable = flags.contains(F::WRITABLE) || match flags.software_state() {
+                SoftwareState::WriteProtected | SoftwareState::SoftDirtyProtected => true,
+                SoftwareState::None | SoftwareState::CopyOnWrite => false,
+            };
+            if writable {
+                mapper.update_flags(page, flags.with_software_state(SoftwareState::CopyOnWrite))
+                    .expect("page vanished").ignore();
+            }
+        }
+    }
+    MapperFlushAll::new()
+}
+
+/// Resolves a write fault on a copy-on-write page.
+///
+/// Allocates a new frame, copies the contents of the old one through `phys` and maps the new
+/// frame writable at `page`. Returns the old frame and the flush promise; the old frame may
+/// still be accessed through stale TLB entries, so its reference must only be dropped with
+/// `SharedFrameAllocator::dec_ref` after the page was flushed.
+pub fn resolve_cow_fault<M, P, A>(mapper: &mut M, page: Page, phys: &mut P, allocator: &mut A) -> Result<(Frame, MapperFlush), CowError>
+    where M: Mapper + Translate, P: PhysRead + PhysWrite, A: SharedFrameAllocator,
+{
+    type F = PageTableFlags;
+    let (old, flags) = match mapper.translate(page.start_address()) {
+        TranslateResult::Mapped { frame, flags, size: PageSize::Size4KiB, .. } => (frame, flags),
+        _ => return Err(CowError::PageNotMapped),
+    };
+    if flags.software_state() != SoftwareState::CopyOnWrite {
+        return Err(CowError::NotCopyOnWrite);
+    }
+    let new = allocator.alloc().ok_or(CowError::FrameAllocationFailed)?;
+    let mut buf = [0u8; 256];
+    for offset in (0..PAGE_SIZE as u32).step_by(buf.len()) {
+        phys.read_phys(PhysAddr::new(old.start_address().as_u32() + offset), &mut buf);
+        phys.write_phys(PhysAddr::new(new.start_address().as_u32() + offset), &buf);
+    }
+    mapper.unmap(page).expect("page vanished").1.ignore();
+    let flush = mapper.map_to(page, new, flags.with_software_state(SoftwareState::None) | F::WRITABLE, allocator)
+        .expect("p1 table vanished");
+    Ok((old, flush))
+}
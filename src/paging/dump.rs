@@ -0,0 +1,52 @@
+use core::fmt::{self, Write};
+use super::page_table::*;
+
+fn p2_range(p2_index: usize) -> (usize, usize) {
+    let start = p2_index << 22;
+    (start, start + (1 << 22) - 1)
+}
+
+/// Writes the line for a p2 entry. `note` replaces the target for special entries.
+pub(crate) fn dump_p2_entry<W: Write>(w: &mut W, p2_index: usize, entry: &PageTableEntry, huge: bool, note: Option<&str>) -> fmt::Result {
+    let (start, end) = p2_range(p2_index);
+    write!(w, "{:#010x}-{:#010x} ", start, end)?;
+    if let Some(note) = note {
+        return writeln!(w, "{}", note);
+    }
+    if huge {
+        write!(w, "4M ")?;
+    }
+    writeln!(w, "{}", entry.describe())
+}
+
+/// Writes the valid entries of a p1 table, merging runs of virtually and physically
+/// contiguous pages with the same flags into one line.
+pub(crate) fn dump_p1<W: Write>(w: &mut W, p2_index: usize, p1: &PageTable) -> fmt::Result {
+    let base = p2_index << 22;
+    let mut run: Option<(usize, usize, PageTableEntry)> = None;
+    for i in 0..=ENTRY_COUNT {
+        let entry = if i < ENTRY_COUNT && p1[i].flags().contains(PageTableFlags::VALID) {
+            Some(p1[i])
+        } else {
+            None
+        };
+        if let Some((first, last, first_entry)) = run {
+            match entry {
+                Some(e) if e.flags() == first_entry.flags()
+                    && e.addr().as_u32() == first_entry.addr().as_u32() + ((i - first) << 12) as u32 => {
+                    run = Some((first, i, first_entry));
+                    continue;
+                }
+                _ => {
+                    writeln!(w, "  {:#010x}-{:#010x} {}", base + (first << 12),
+                             base + (last << 12) + 0xfff, first_entry.describe())?;
+                    run = None;
+                }
+            }
+        }
+        if let Some(entry) = entry {
+            run = Some((i, i, entry));
+        }
+    }
+    Ok(())
+}
@@ -1,6 +1,8 @@
 //! Traits for abstracting away frame allocation and deallocation.
 
-use addr::Frame;
+use addr::*;
+#[cfg(feature = "alloc")]
+use super::metadata::FrameMetadata;
 
 /// A trait for types that can allocate a frame of memory.
 pub trait FrameAllocator {
@@ -13,3 +15,306 @@ pub trait FrameDeallocator {
     /// Deallocate the given frame of memory.
     fn dealloc(&mut self, frame: Frame);
 }
+
+/// A frame allocator that keeps a reference count per frame, so frames can be shared by
+/// several mappings, e.g. after `fork`.
+///
+/// Frames returned by `alloc` start with one reference.
+pub trait SharedFrameAllocator: FrameAllocator {
+    /// Adds a reference to `frame`.
+    fn inc_ref(&mut self, frame: Frame);
+
+    /// Drops a reference to `frame` and frees it if this was the last one.
+    ///
+    /// Returns whether the frame was freed.
+    fn dec_ref(&mut self, frame: Frame) -> bool;
+
+    /// Returns whether `frame` was handed out by this allocator and is reference counted.
+    ///
+    /// Frames that are mapped but not owned, e.g. MMIO, firmware memory or the shared zero
+    /// page, must not be passed to `inc_ref` or `dec_ref`.
+    fn owns(&self, frame: Frame) -> bool;
+}
+
+/// Adds reference counting to a frame allocator, using one counter per frame stored in a
+/// caller provided slice.
+#[cfg(feature = "alloc")]
+pub struct RefCountedFrames<'a, A> {
+    inner: A,
+    counts: FrameMetadata<'a, u16>,
+}
+
+#[cfg(feature = "alloc")]
+impl<'a, A> RefCountedFrames<'a, A> where A: FrameAllocator + FrameDeallocator {
+    /// Creates a reference counting wrapper around `inner`.
+    ///
+    /// `counts[i]` holds the count of the frame `start + i`; `inner` must only return frames
+    /// in that range. The counts are reset to zero.
+    pub fn new(inner: A, counts: &'a mut [u16], start: Frame) -> Self {
+        let mut counts = FrameMetadata::new(counts, start);
+        counts.fill(0);
+        RefCountedFrames { inner, counts }
+    }
+
+    /// Returns the number of references to `frame`.
+    pub fn ref_count(&self, frame: Frame) -> usize {
+        self.counts[frame] as usize
+    }
+
+    fn count_mut(&mut self, frame: Frame) -> &mut u16 {
+        &mut self.counts[frame]
+    }
+}
+
+#[cfg(feature = "alloc")]
+impl<'a, A> FrameAllocator for RefCountedFrames<'a, A> where A: FrameAllocator + FrameDeallocator {
+    fn alloc(&mut self) -> Option<Frame> {
+        let frame = self.inner.alloc()?;
+        *self.count_mut(frame) = 1;
+        Some(frame)
+    }
+}
+
+#[cfg(feature = "alloc")]
+impl<'a, A> SharedFrameAllocator for RefCountedFrames<'a, A> where A: FrameAllocator + FrameDeallocator {
+    fn inc_ref(&mut self, frame: Frame) {
+        let count = self.count_mut(frame);
+        assert!(*count != 0, "reference to a free frame");
+        *count += 1;
+    }
+
+    fn dec_ref(&mut self, frame: Frame) -> bool {
+        {
+            let count = self.count_mut(frame);
+            assert!(*count != 0, "reference to a free frame");
+            *count -= 1;
+            if *count != 0 {
+                return false;
+            }
+        }
+        self.inner.dealloc(frame);
+        true
+    }
+
+    fn owns(&self, frame: Frame) -> bool {
+        self.counts.get(frame).map_or(false, |&count| count != 0)
+    }
+}
+
+/// A frame allocator handing out the frames of a list of physical memory regions in order,
+/// e.g. the usable memory reported by firmware, skipping reserved holes such as the kernel
+/// image or the device tree.
+///
+/// Frames are never reused, so this is mostly useful during boot, before a real allocator is
+/// set up.
+#[cfg(feature = "alloc")]
+pub struct BumpFrameAllocator<'a> {
+    regions: &'a [PhysRange],
+    reserved: &'a [PhysRange],
+    current: FrameRangeInclusive,
+    next_region: usize,
+}
+
+#[cfg(feature = "alloc")]
+impl<'a> BumpFrameAllocator<'a> {
+    /// Creates an allocator for the frames lying completely inside `regions`, in the given
+    /// order, except the frames overlapping any range in `reserved`.
+    pub fn new(regions: &'a [PhysRange], reserved: &'a [PhysRange]) -> Self {
+        let empty = PhysRange::new(PhysAddr::new(0), 0);
+        BumpFrameAllocator { regions, reserved, current: empty.frames(), next_region: 0 }
+    }
+}
+
+#[cfg(feature = "alloc")]
+impl<'a> FrameAllocator for BumpFrameAllocator<'a> {
+    fn alloc(&mut self) -> Option<Frame> {
+        loop {
+            while !self.current.is_empty() {
+                let frame = self.current.start;
+                match self.reserved.iter().map(|r| r.frames()).find(|hole| hole.contains(frame)) {
+                    Some(hole) => {
+                        // Skip past the last frame of the hole, which may be the last frame below 4G.
+                        self.current = Frame::range_inclusive(hole.end, self.current.end);
+                        self.current.next();
+                    }
+                    None => return self.current.next(),
+                }
+            }
+            let region = self.regions.get(self.next_region)?;
+            self.current = region.contained_frames();
+            self.next_region += 1;
+        }
+    }
+}
+
+#[cfg(feature = "alloc")]
+const BITS: usize = ::core::mem::size_of::<usize>() * 8;
+
+/// A frame allocator for a contiguous range of frames, using one bit per frame stored in a
+/// caller provided slice. Freed frames can be allocated again.
+///
+/// Allocation is first-fit. With `set_rotating`, the search starts after the last allocated
+/// frame instead of at the start of the range, which spreads allocations and skips the full
+/// part of the bitmap.
+#[cfg(feature = "alloc")]
+pub struct BitmapFrameAllocator<'a> {
+    bitmap: &'a mut [usize],
+    start: usize,
+    frames: usize,
+    cursor: usize,
+    rotating: bool,
+}
+
+#[cfg(feature = "alloc")]
+impl<'a> BitmapFrameAllocator<'a> {
+    /// Creates an allocator for the frames in `frames`, which are all free initially.
+    ///
+    /// `bitmap` needs one bit per frame; a set bit marks an allocated frame.
+    pub fn new(bitmap: &'a mut [usize], frames: FrameRange) -> Self {
+        let len = frames.len();
+        assert!(bitmap.len() * BITS >= len, "bitmap too small for the frame range");
+        for word in bitmap.iter_mut() {
+            *word = 0;
+        }
+        BitmapFrameAllocator { bitmap, start: frames.start.number(), frames: len, cursor: 0, rotating: false }
+    }
+
+    /// Selects whether the search for a free frame starts after the last allocated frame.
+    pub fn set_rotating(&mut self, rotating: bool) {
+        self.rotating = rotating;
+    }
+
+    /// Marks the frames in `frames` as allocated, e.g. the kernel image. Frames outside the
+    /// managed range are ignored.
+    pub fn reserve(&mut self, frames: FrameRange) {
+        for frame in frames {
+            if let Some(index) = self.index(frame) {
+                self.bitmap[index / BITS] |= 1 << (index % BITS);
+            }
+        }
+    }
+
+    /// Returns the number of free frames.
+    pub fn free_frames(&self) -> usize {
+        (0..self.frames).filter(|&index| !self.is_allocated(index)).count()
+    }
+
+    fn index(&self, frame: Frame) -> Option<usize> {
+        let index = frame.number().checked_sub(self.start)?;
+        if index < self.frames { Some(index) } else { None }
+    }
+
+    fn is_allocated(&self, index: usize) -> bool {
+        self.bitmap[index / BITS] & 1 << (index % BITS) != 0
+    }
+
+    /// Returns the first free frame index in `[from, to)`.
+    fn find_free(&self, from: usize, to: usize) -> Option<usize> {
+        let mut index = from;
+        while index < to {
+            if index % BITS == 0 && self.bitmap[index / BITS] == !0 {
+                index += BITS;
+                continue;
+            }
+            if !self.is_allocated(index) {
+                return Some(index);
+            }
+            index += 1;
+        }
+        None
+    }
+}
+
+#[cfg(feature = "alloc")]
+impl<'a> FrameAllocator for BitmapFrameAllocator<'a> {
+    fn alloc(&mut self) -> Option<Frame> {
+        let from = if self.rotating { self.cursor } else { 0 };
+        let index = self.find_free(from, self.frames).or_else(|| self.find_free(0, from))?;
+        self.bitmap[index / BITS] |= 1 << (index % BITS);
+        self.cursor = index + 1;
+        Some(Frame::of_addr(PhysAddr::new(((self.start + index) * PAGE_SIZE) as u32)))
+    }
+}
+
+#[cfg(feature = "alloc")]
+impl<'a> FrameDeallocator for BitmapFrameAllocator<'a> {
+    fn dealloc(&mut self, frame: Frame) {
+        let index = self.index(frame).expect("frame outside of the allocator's range");
+        assert!(self.is_allocated(index), "double free of a frame");
+        self.bitmap[index / BITS] &= !(1 << (index % BITS));
+    }
+}
+
+#[cfg(all(test, feature = "alloc"))]
+mod tests {
+    use super::*;
+
+    fn frame(index: u32) -> Frame {
+        Frame::of_addr(PhysAddr::new(0x8000_0000 + index * PAGE_SIZE as u32))
+    }
+
+    #[test]
+    fn bitmap_alloc_and_dealloc() {
+        let mut bitmap = [!0; 3];
+        let count = BITS as u32 + 6;
+        let mut allocator = BitmapFrameAllocator::new(&mut bitmap, FrameRange::new(frame(0), frame(count)));
+        assert_eq!(allocator.free_frames(), count as usize);
+
+        // A full first word is skipped as a whole.
+        allocator.reserve(FrameRange::new(frame(0), frame(BITS as u32)));
+        for index in BITS as u32..count {
+            assert_eq!(allocator.alloc(), Some(frame(index)));
+        }
+        assert_eq!(allocator.alloc(), None);
+        assert_eq!(allocator.free_frames(), 0);
+
+        allocator.dealloc(frame(BITS as u32 + 2));
+        assert_eq!(allocator.free_frames(), 1);
+        assert_eq!(allocator.alloc(), Some(frame(BITS as u32 + 2)));
+    }
+
+    #[test]
+    fn bitmap_reserve_ignores_outside_frames() {
+        let mut bitmap = [0; 1];
+        let mut allocator = BitmapFrameAllocator::new(&mut bitmap, FrameRange::new(frame(4), frame(8)));
+        allocator.reserve(FrameRange::new(frame(0), frame(5)));
+        allocator.reserve(FrameRange::new(frame(7), frame(12)));
+        assert_eq!(allocator.free_frames(), 2);
+        assert_eq!(allocator.alloc(), Some(frame(5)));
+        assert_eq!(allocator.alloc(), Some(frame(6)));
+        assert_eq!(allocator.alloc(), None);
+    }
+
+    #[test]
+    fn bitmap_rotating() {
+        let mut bitmap = [0; 1];
+        let mut allocator = BitmapFrameAllocator::new(&mut bitmap, FrameRange::new(frame(0), frame(4)));
+        allocator.set_rotating(true);
+        assert_eq!(allocator.alloc(), Some(frame(0)));
+        assert_eq!(allocator.alloc(), Some(frame(1)));
+        allocator.dealloc(frame(0));
+        assert_eq!(allocator.alloc(), Some(frame(2)));
+        assert_eq!(allocator.alloc(), Some(frame(3)));
+        // The search wraps around to the freed frame.
+        assert_eq!(allocator.alloc(), Some(frame(0)));
+        assert_eq!(allocator.alloc(), None);
+    }
+
+    #[test]
+    #[should_panic(expected = "double free")]
+    fn bitmap_double_free() {
+        let mut bitmap = [0; 1];
+        let mut allocator = BitmapFrameAllocator::new(&mut bitmap, FrameRange::new(frame(0), frame(4)));
+        let allocated = allocator.alloc().unwrap();
+        allocator.dealloc(allocated);
+        allocator.dealloc(allocated);
+    }
+
+    #[test]
+    #[should_panic(expected = "outside of the allocator's range")]
+    fn bitmap_dealloc_outside_range() {
+        let mut bitmap = [0; 1];
+        let mut allocator = BitmapFrameAllocator::new(&mut bitmap, FrameRange::new(frame(0), frame(4)));
+        allocator.dealloc(frame(4));
+    }
+}
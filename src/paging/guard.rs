@@ -0,0 +1,33 @@
+use super::frame_alloc::*;
+use super::page_table::*;
+use super::phys::*;
+use super::recursive::*;
+use super::reserve::*;
+use addr::*;
+
+/// Maps every page in `pages` to a newly allocated frame, zeroed through `phys`, and puts
+/// `guard_count` guard pages directly below them, e.g. for a stack.
+///
+/// Guard pages are left non-present and tagged, see `PageTableEntry::set_guard`, so the fault
+/// handler can use `is_guard` to tell a stack overflow from a wild pointer. The pages are
+/// mapped with `Mapper::map_range_alloc`.
+///
+/// Returns `AddressOverflow` if the guard pages would lie below address 0 and
+/// `PageAlreadyMapped` if a page or guard page is in use. Pages handled before the failure
+/// stay mapped.
+pub fn map_with_guard<M, P, A>(mapper: &mut M, pages: PageRange, guard_count: usize, flags: PageTableFlags, phys: &mut P, allocator: &mut A) -> Result<MapperFlushAll, MapToError>
+    where M: LeafEntries, P: PhysWrite, A: FrameAllocator + FrameDeallocator,
+{
+    let guard_start = guard_count.checked_mul(PAGE_SIZE)
+        .and_then(|len| pages.start.start_address().as_usize().checked_sub(len))
+        .ok_or(MapToError::AddressOverflow)?;
+    for page in Page::range(VirtAddr::new(guard_start), pages.start.start_address()) {
+        set_non_present(mapper, page, allocator, |entry| entry.set_guard())?;
+    }
+    mapper.map_range_alloc(pages, flags, phys, allocator)
+}
+
+/// Returns whether `page` is a guard page set up by `map_with_guard`.
+pub fn is_guard<M: LeafEntries>(mapper: &mut M, page: Page) -> bool {
+    mapper.leaf_entry_mut(page, |entry| entry.is_guard()).unwrap_or(false)
+}
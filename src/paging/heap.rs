@@ -0,0 +1,89 @@
+use super::frame_alloc::*;
+use super::page_table::*;
+use super::phys::*;
+use super::recursive::*;
+use addr::*;
+
+/// A kernel heap growing upwards inside a reserved range of pages.
+///
+/// Only `[start, end)` is mapped. The page at `end` is never mapped, so it acts as a guard page:
+/// running off the end of the heap faults instead of corrupting whatever follows.
+#[derive(Debug)]
+pub struct KernelHeap {
+    range: PageRange,
+    end: Page,
+    flags: PageTableFlags,
+}
+
+/// An error returned when mapping or growing a `KernelHeap` fails.
+#[derive(Debug)]
+pub enum HeapError {
+    /// The heap would not leave room for its guard page inside the reserved range.
+    OutOfRange,
+    /// Mapping a heap page failed.
+    MapFailed(MapToError),
+}
+
+impl KernelHeap {
+    /// Reserves `range` for a heap and maps its first `pages` pages with frames zeroed through
+    /// `phys`.
+    pub fn map_heap<M, P, A>(mapper: &mut M, range: PageRange, pages: usize, flags: PageTableFlags, phys: &mut P, allocator: &mut A) -> Result<Self, HeapError>
+        where M: Mapper, P: PhysWrite, A: FrameAllocator + FrameDeallocator,
+    {
+        let mut heap = KernelHeap { range, end: range.start, flags };
+        heap.grow(mapper, pages, phys, allocator)?;
+        Ok(heap)
+    }
+
+    /// Maps `pages` more zeroed pages at the end of the heap and moves the guard page up.
+    ///
+    /// If a page can't be mapped, its frame is deallocated again and the heap keeps the pages
+    /// mapped before it. The new pages are flushed from the TLB in one batch at the end, also
+    /// when mapping fails midway.
+    pub fn grow<M, P, A>(&mut self, mapper: &mut M, pages: usize, phys: &mut P, allocator: &mut A) -> Result<(), HeapError>
+        where M: Mapper, P: PhysWrite, A: FrameAllocator + FrameDeallocator,
+    {
+        if self.size() / PAGE_SIZE + pages >= self.range.len() {
+            return Err(HeapError::OutOfRange);
+        }
+        let mut batch = FlushBatch::new();
+        let mut result = Ok(());
+        for _ in 0..pages {
+            let page = self.end;
+            let frame = match allocator.alloc() {
+                Some(frame) => frame,
+                None => {
+                    result = Err(HeapError::MapFailed(MapToError::FrameAllocationFailed));
+                    break;
+                }
+            };
+            phys.clear_frame(frame);
+            match mapper.map_to(page, frame, self.flags, allocator) {
+                Ok(flush) => batch.push(flush),
+                Err(err) => {
+                    allocator.dealloc(frame);
+                    result = Err(HeapError::MapFailed(err));
+                    break;
+                }
+            }
+            self.end = Page::of_addr(VirtAddr::new(page.start_address().as_usize() + PAGE_SIZE));
+        }
+        batch.flush();
+        result
+    }
+
+    /// Returns the start address of the heap.
+    pub fn start(&self) -> VirtAddr {
+        self.range.start.start_address()
+    }
+
+    /// Returns the first address after the mapped part of the heap, i.e. the guard page.
+    pub fn end(&self) -> VirtAddr {
+        self.end.start_address()
+    }
+
+    /// Returns the size of the mapped part of the heap in bytes.
+    pub fn size(&self) -> usize {
+        self.end().as_usize() - self.start().as_usize()
+    }
+}
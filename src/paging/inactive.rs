@@ -0,0 +1,156 @@
+use core::ops::{Deref, DerefMut};
+use super::frame_alloc::*;
+use super::page_table::*;
+use super::recursive::*;
+use addr::*;
+
+/// A page table that is not loaded into satp, e.g. the address space of a new process.
+///
+/// The table is edited through the active `RecursivePageTable` by temporarily pointing the
+/// active recursive entry to the inactive table, so the recursive window shows the inactive
+/// table instead. All other mappings stay those of the active table.
+#[derive(Debug)]
+pub struct InactivePageTable {
+    p2_frame: Frame,
+}
+
+impl InactivePageTable {
+    /// Turns `frame` into an empty inactive page table with the same recursive indices as
+    /// `active`.
+    ///
+    /// `temp_page` must be an unused page; it is used to access `frame` and is unmapped again.
+    pub fn new<A>(frame: Frame, active: &mut RecursivePageTable, temp_page: Page, allocator: &mut A) -> Result<Self, MapToError>
+        where A: FrameAllocator,
+    {
+        type F = PageTableFlags;
+        let (recursive_index, rw_index) = active.recursive_indices();
+        active.map_to(temp_page, frame, F::VALID | F::READABLE | F::WRITABLE, allocator)?.flush();
+        {
+            let table = unsafe { &mut *(temp_page.start_address().as_usize() as *mut PageTable) };
+            table.zero();
+            table.set_recursive_at(recursive_index, rw_index, frame);
+        }
+        active.unmap(temp_page).expect("temporary page vanished").1.flush();
+        Ok(InactivePageTable { p2_frame: frame })
+    }
+
+    /// Wraps an existing inactive page table whose recursive entries are set up like the ones
+    /// of the active table it will be edited with.
+    pub unsafe fn from_frame(p2_frame: Frame) -> Self {
+        InactivePageTable { p2_frame }
+    }
+
+    /// Returns the frame of the level 2 table, e.g. to build the satp value.
+    pub fn frame(&self) -> Frame {
+        self.p2_frame
+    }
+
+    /// Runs `f` with `active` redirected to this table.
+    ///
+    /// Inside `f`, all `Mapper` operations on `active` modify the inactive table. `temp_page`
+    /// must be an unused page of the active table outside the recursive window, otherwise
+    /// `ReservedIndex` is returned; it is used to restore the active recursive entry afterwards,
+    /// which also happens if `f` panics.
+    pub fn edit<A, F, T>(&mut self, active: &mut RecursivePageTable, temp_page: Page, allocator: &mut A, f: F) -> Result<T, MapToError>
+        where A: FrameAllocator, F: FnOnce(&mut RecursivePageTable) -> T,
+    {
+        let mut redirected = redirect(active, self.p2_frame, temp_page, allocator)?;
+        Ok(f(&mut redirected))
+    }
+}
+
+/// An error returned by `RecursivePageTable::new_for_root`.
+#[derive(Debug)]
+pub enum NewForRootError {
+    /// The recursive entries of the table don't match the ones of the active table, or the
+    /// table is the active one.
+    NotRecursivelyMapped,
+    /// Mapping the active table at the temporary page failed.
+    MapFailed(MapToError),
+}
+
+impl<'a> RecursivePageTable<'a> {
+    /// Creates a RecursivePageTable for the page table in `frame`, which need not be active,
+    /// e.g. the table of a child address space.
+    ///
+    /// `root` is the table in `frame`, e.g. accessed through a physmap. Like `new`, this checks
+    /// its recursive entries, which must be at the indices of `active`, but skips the satp
+    /// comparison. The table is then edited the way `InactivePageTable::edit` does it, by
+    /// redirecting the recursive entry of `active` until the returned table is dropped;
+    /// `temp_page` must be an unused page of `active` outside the recursive window.
+    pub fn new_for_root<'b, A>(frame: Frame, root: &PageTable, active: &'b mut RecursivePageTable<'a>, temp_page: Page, allocator: &mut A) -> Result<RedirectedPageTable<'b, 'a>, NewForRootError>
+        where A: FrameAllocator,
+    {
+        type F = PageTableFlags;
+        let (recursive_index, rw_index) = active.recursive_indices();
+        if frame == active.p2_table()[recursive_index].frame()
+            || frame != root[recursive_index].frame()
+            || frame != root[rw_index].frame()
+            || !root[recursive_index].flags().contains(F::VALID)
+            ||  root[recursive_index].flags().intersects(F::READABLE | F::WRITABLE | F::EXECUTABLE)
+            || !root[rw_index].flags().contains(F::VALID | F::READABLE | F::WRITABLE)
+        {
+            return Err(NewForRootError::NotRecursivelyMapped);
+        }
+        redirect(active, frame, temp_page, allocator).map_err(NewForRootError::MapFailed)
+    }
+}
+
+/// Points the recursive entry of `active` to the page table in `frame`, after mapping the
+/// active table at `temp_page` so the entry can be restored.
+fn redirect<'b, 'a, A>(active: &'b mut RecursivePageTable<'a>, frame: Frame, temp_page: Page, allocator: &mut A) -> Result<RedirectedPageTable<'b, 'a>, MapToError>
+    where A: FrameAllocator,
+{
+    use asm::sfence_vma_all;
+    type Flags = PageTableFlags;
+    let (recursive_index, rw_index) = active.recursive_indices();
+    if temp_page.p2_index() == recursive_index || temp_page.p2_index() == rw_index {
+        return Err(MapToError::ReservedIndex);
+    }
+    let active_frame = active.p2_table()[recursive_index].frame();
+    active.map_to(temp_page, active_frame, Flags::VALID | Flags::READABLE | Flags::WRITABLE, allocator)?.flush();
+
+    active.p2_table_mut()[recursive_index].set(frame, Flags::VALID);
+    sfence_vma_all();
+    Ok(RedirectedPageTable { active, temp_page, recursive_index, active_frame })
+}
+
+/// The active `RecursivePageTable` with its recursive entry pointing to another page table,
+/// returned by `RecursivePageTable::new_for_root`.
+///
+/// All `Mapper` operations through it modify the other table. When it is dropped, also while
+/// unwinding, the recursive entry is pointed back to the active table and the temporary page
+/// is unmapped.
+pub struct RedirectedPageTable<'b, 'a: 'b> {
+    active: &'b mut RecursivePageTable<'a>,
+    temp_page: Page,
+    recursive_index: usize,
+    active_frame: Frame,
+}
+
+impl<'b, 'a> Deref for RedirectedPageTable<'b, 'a> {
+    type Target = RecursivePageTable<'a>;
+
+    fn deref(&self) -> &RecursivePageTable<'a> {
+        self.active
+    }
+}
+
+impl<'b, 'a> DerefMut for RedirectedPageTable<'b, 'a> {
+    fn deref_mut(&mut self) -> &mut RecursivePageTable<'a> {
+        self.active
+    }
+}
+
+impl<'b, 'a> Drop for RedirectedPageTable<'b, 'a> {
+    fn drop(&mut self) {
+        use asm::sfence_vma_all;
+        let active_p2 = unsafe { &mut *(self.temp_page.start_address().as_usize() as *mut PageTable) };
+        active_p2[self.recursive_index].set(self.active_frame, PageTableFlags::VALID);
+        sfence_vma_all();
+        // Don't panic while unwinding, the recursive entry is restored anyway.
+        if let Ok((_, flush)) = self.active.unmap(self.temp_page) {
+            flush.flush();
+        }
+    }
+}
@@ -0,0 +1,130 @@
+use super::frame_alloc::*;
+use super::page_table::*;
+use super::recursive::*;
+use addr::*;
+
+/// A change of one leaf entry, recorded by `JournalMapper`.
+#[derive(Debug, Copy, Clone)]
+pub struct JournalRecord {
+    pub page: Page,
+    /// The entry before the change; unused if the page was not mapped by a valid 4K page.
+    pub old: PageTableEntry,
+    /// The entry after the change; unused if the page was unmapped.
+    pub new: PageTableEntry,
+}
+
+/// A mapper wrapper that records every change of a leaf entry in a ring buffer.
+///
+/// This enables dirty logging for live migration or incremental snapshots: the consumer
+/// periodically calls `drain` to process the changes since the last call. If the ring buffer
+/// is full, the oldest records are overwritten and counted by `lost`.
+///
+/// Only changes made through this wrapper are recorded. Bulk operations use the per-page
+/// default implementations of `Mapper`, so that every page is recorded.
+pub struct JournalMapper<'a, M: Mapper + Translate> {
+    inner: M,
+    ring: &'a mut [JournalRecord],
+    /// Index of the oldest record.
+    head: usize,
+    len: usize,
+    lost: usize,
+}
+
+impl<'a, M: Mapper + Translate> JournalMapper<'a, M> {
+    /// Wraps `inner`, recording changes into `ring`.
+    ///
+    /// Panics if `ring` is empty.
+    pub fn new(inner: M, ring: &'a mut [JournalRecord]) -> Self {
+        assert!(!ring.is_empty(), "journal ring buffer is empty");
+        JournalMapper { inner, ring, head: 0, len: 0, lost: 0 }
+    }
+
+    /// Returns the wrapped mapper.
+    pub fn into_inner(self) -> M {
+        self.inner
+    }
+
+    /// Returns the wrapped mapper, e.g. to make changes that should not be recorded.
+    pub fn inner_mut(&mut self) -> &mut M {
+        &mut self.inner
+    }
+
+    /// Returns the number of records that were overwritten since the last `drain`.
+    pub fn lost(&self) -> usize {
+        self.lost
+    }
+
+    /// Calls `f` with every record, oldest first, and empties the journal.
+    pub fn drain<F>(&mut self, mut f: F) where F: FnMut(&JournalRecord) {
+        for i in 0..self.len {
+            f(&self.ring[(self.head + i) % self.ring.len()]);
+        }
+        self.head = 0;
+        self.len = 0;
+        self.lost = 0;
+    }
+
+    fn entry(&self, page: Page) -> PageTableEntry {
+        match self.inner.translate(page.start_address()) {
+            TranslateResult::Mapped { frame, flags, size: PageSize::Size4KiB, .. } =>
+                PageTableEntry::new(frame, flags),
+            _ => PageTableEntry::unused(),
+        }
+    }
+
+    fn record(&mut self, page: Page, old: PageTableEntry, new: PageTableEntry) {
+        let record = JournalRecord { page, old, new };
+        let cap = self.ring.len();
+        if self.len == cap {
+            self.head = (self.head + 1) % cap;
+            self.len -= 1;
+            self.lost += 1;
+        }
+        self.ring[(self.head + self.len) % cap] = record;
+        self.len += 1;
+    }
+}
+
+impl<'a, M: Mapper + Translate> Mapper for JournalMapper<'a, M> {
+    fn map_to_with_table_flags<A>(&mut self, page: Page, frame: Frame, flags: PageTableFlags, table_flags: PageTableFlags, allocator: &mut A) -> Result<MapperFlush, MapToError>
+        where A: FrameAllocator,
+    {
+        let flush = self.inner.map_to_with_table_flags(page, frame, flags, table_flags, allocator)?;
+        self.record(page, PageTableEntry::unused(), PageTableEntry::new(frame, flags));
+        Ok(flush)
+    }
+
+    fn unmap(&mut self, page: Page) -> Result<(Frame, MapperFlush), UnmapError> {
+        let old = self.entry(page);
+        let ret = self.inner.unmap(page)?;
+        self.record(page, old, PageTableEntry::unused());
+        Ok(ret)
+    }
+
+    fn update_flags(&mut self, page: Page, flags: PageTableFlags) -> Result<MapperFlush, FlagUpdateError> {
+        check_leaf_flags(flags)?;
+        let old = self.entry(page);
+        let flush = self.inner.update_flags(page, flags)?;
+        let frame = self.inner.translate_page(page).expect("page vanished");
+        self.record(page, old, PageTableEntry::new(frame, flags));
+        Ok(flush)
+    }
+
+    fn translate_page(&self, page: Page) -> Option<Frame> {
+        self.inner.translate_page(page)
+    }
+}
+
+impl<'a, M: Mapper + Translate> Translate for JournalMapper<'a, M> {
+    fn translate(&self, addr: VirtAddr) -> TranslateResult {
+        self.inner.translate(addr)
+    }
+
+    fn region_mapped(&self, addr: VirtAddr) -> bool {
+        self.inner.region_mapped(addr)
+    }
+
+    fn leaf_entry(&self, page: Page) -> Option<PageTableEntry> {
+        self.inner.leaf_entry(page)
+    }
+}
@@ -0,0 +1,91 @@
+use super::frame_alloc::*;
+use super::layout::*;
+use super::page_table::*;
+use super::recursive::*;
+use addr::*;
+
+/// Maps physical memory into the kernel half of the address space at a fixed offset, as done by
+/// higher-half kernels for their image and the linear mapping of RAM.
+///
+/// Sv32 has no non-canonical addresses; instead every mapping is checked to lie completely in
+/// one region of the `MemoryLayout`, so a bad offset can't clobber user space or other regions.
+#[derive(Debug, Copy, Clone)]
+pub struct KernelMapper {
+    offset: usize,
+    region: Region,
+}
+
+/// An error returned by `KernelMapper::map_range`.
+#[derive(Debug)]
+pub enum KernelMapError {
+    /// Part of the range would be mapped outside the region of the `KernelMapper`.
+    NotKernelAddress,
+    /// Mapping a page failed.
+    MapFailed(MapToError),
+}
+
+impl KernelMapper {
+    /// Creates a KernelMapper for the linear mapping of physical memory, mapping physical
+    /// address `phys_start` at the start of `layout.physmap`.
+    pub fn physmap(layout: &MemoryLayout, phys_start: PhysAddr) -> Self {
+        Self::for_region(layout.physmap, phys_start)
+    }
+
+    /// Creates a KernelMapper for the kernel image, mapping physical address `phys_start` at
+    /// the start of `layout.kernel`.
+    pub fn kernel_image(layout: &MemoryLayout, phys_start: PhysAddr) -> Self {
+        Self::for_region(layout.kernel, phys_start)
+    }
+
+    fn for_region(region: Region, phys_start: PhysAddr) -> Self {
+        let offset = region.start.as_usize().wrapping_sub(phys_start.as_u32() as usize);
+        KernelMapper { offset, region }
+    }
+
+    /// Returns the offset between physical and virtual addresses, modulo the address space.
+    pub fn offset(&self) -> usize {
+        self.offset
+    }
+
+    /// Returns the region of virtual memory the mappings are confined to.
+    pub fn region(&self) -> Region {
+        self.region
+    }
+
+    /// Returns the virtual address of `addr`, or `None` if it lies outside the region.
+    pub fn phys_to_virt(&self, addr: PhysAddr) -> Option<VirtAddr> {
+        let virt = VirtAddr::new((addr.as_u32() as usize).wrapping_add(self.offset));
+        if self.region.contains(virt) { Some(virt) } else { None }
+    }
+
+    /// Returns the physical address of `addr`, or `None` if it lies outside the region.
+    pub fn virt_to_phys(&self, addr: VirtAddr) -> Option<PhysAddr> {
+        if !self.region.contains(addr) {
+            return None;
+        }
+        Some(PhysAddr::new(addr.as_usize().wrapping_sub(self.offset) as u32))
+    }
+
+    /// Maps all frames overlapping `range` at their kernel address.
+    ///
+    /// The mappings and all newly created p1 tables are `GLOBAL`, as the kernel half is shared
+    /// by all address spaces. The whole range is checked before anything is mapped.
+    pub fn map_range<M, A>(&self, mapper: &mut M, range: PhysRange, flags: PageTableFlags, allocator: &mut A) -> Result<MapperFlushAll, KernelMapError>
+        where M: Mapper, A: FrameAllocator,
+    {
+        type F = PageTableFlags;
+        let frames = range.frames();
+        if !frames.is_empty() {
+            let last = PhysAddr::new(frames.end.start_address().as_u32() + (PAGE_SIZE as u32 - 1));
+            if self.phys_to_virt(frames.start.start_address()).is_none() || self.phys_to_virt(last).is_none() {
+                return Err(KernelMapError::NotKernelAddress);
+            }
+        }
+        for frame in frames {
+            let page = Page::of_addr(self.phys_to_virt(frame.start_address()).unwrap());
+            mapper.map_to_with_table_flags(page, frame, flags | F::GLOBAL, F::VALID | F::GLOBAL, allocator)
+                .map_err(KernelMapError::MapFailed)?.ignore();
+        }
+        Ok(MapperFlushAll::new())
+    }
+}
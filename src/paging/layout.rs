@@ -0,0 +1,125 @@
+//! Description of the kernel virtual address space layout.
+
+use addr::*;
+
+/// A page aligned region of virtual memory.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Region {
+    pub start: VirtAddr,
+    /// Size of the region in bytes.
+    pub size: usize,
+}
+
+impl Region {
+    pub fn new(start: VirtAddr, size: usize) -> Self {
+        Region { start, size }
+    }
+
+    /// Returns the last address of the region.
+    ///
+    /// Returns `None` if the region is empty or wraps around the address space.
+    pub fn last(&self) -> Option<VirtAddr> {
+        if self.size == 0 {
+            return None;
+        }
+        self.start.as_usize().checked_add(self.size - 1).map(VirtAddr::new)
+    }
+
+    /// Returns whether `addr` lies inside the region.
+    pub fn contains(&self, addr: VirtAddr) -> bool {
+        match self.last() {
+            Some(last) => self.start <= addr && addr <= last,
+            None => false,
+        }
+    }
+
+    /// Returns whether the two regions share at least one address.
+    pub fn overlaps(&self, other: &Region) -> bool {
+        match (self.last(), other.last()) {
+            (Some(last), Some(other_last)) => self.start <= other_last && other.start <= last,
+            _ => false,
+        }
+    }
+}
+
+/// Names the regions of a `MemoryLayout`, used to report validation errors.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum LayoutRegion {
+    Kernel,
+    Physmap,
+    Fixmap,
+    Vmalloc,
+    User,
+}
+
+/// An error returned from `MemoryLayout::validate`.
+#[derive(Debug)]
+pub enum LayoutError {
+    /// The region has a size of zero.
+    Empty(LayoutRegion),
+    /// The start or size of the region is not page aligned.
+    Misaligned(LayoutRegion),
+    /// The region wraps around the end of the address space.
+    OutOfRange(LayoutRegion),
+    /// The two regions overlap.
+    Overlap(LayoutRegion, LayoutRegion),
+}
+
+/// The virtual memory layout of a kernel.
+///
+/// User space occupies `[0, user_ceiling)`, all other regions belong to the kernel.
+#[derive(Debug, Copy, Clone)]
+pub struct MemoryLayout {
+    /// The kernel image.
+    pub kernel: Region,
+    /// Linear mapping of physical memory.
+    pub physmap: Region,
+    /// Fixed mappings set up at boot.
+    pub fixmap: Region,
+    /// Area for dynamically mapped kernel memory.
+    pub vmalloc: Region,
+    /// The first address above user space.
+    pub user_ceiling: VirtAddr,
+}
+
+impl MemoryLayout {
+    /// Returns the user space region.
+    pub fn user(&self) -> Region {
+        Region::new(VirtAddr::new(0), self.user_ceiling.as_usize())
+    }
+
+    /// Returns whether `addr` belongs to user space.
+    pub fn is_user(&self, addr: VirtAddr) -> bool {
+        addr < self.user_ceiling
+    }
+
+    /// Checks that all regions are non-empty, page aligned and pairwise disjoint.
+    pub fn validate(&self) -> Result<(), LayoutError> {
+        let regions = [
+            (LayoutRegion::Kernel, self.kernel),
+            (LayoutRegion::Physmap, self.physmap),
+            (LayoutRegion::Fixmap, self.fixmap),
+            (LayoutRegion::Vmalloc, self.vmalloc),
+            (LayoutRegion::User, self.user()),
+        ];
+        for &(name, region) in regions.iter() {
+            if region.size == 0 {
+                return Err(LayoutError::Empty(name));
+            }
+            if region.start.page_offset() != 0 || region.size % PAGE_SIZE != 0 {
+                return Err(LayoutError::Misaligned(name));
+            }
+            if region.last().is_none() {
+                return Err(LayoutError::OutOfRange(name));
+            }
+        }
+        for (i, &(name, region)) in regions.iter().enumerate() {
+            for &(other_name, other) in regions[i + 1..].iter() {
+                if region.overlaps(&other) {
+                    return Err(LayoutError::Overlap(name, other_name));
+                }
+            }
+        }
+        Ok(())
+    }
+}
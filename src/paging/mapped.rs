@@ -0,0 +1,416 @@
+use core::fmt;
+use super::dump::*;
+use super::frame_alloc::*;
+use super::page_table::*;
+use super::recursive::*;
+use super::verify::*;
+use super::walk::{FlagsSummary, PageTableStats};
+use addr::*;
+
+/// A trait for types that can locate page table frames in virtual memory.
+pub trait PhysToVirt {
+    /// Returns a pointer through which the page table stored in `frame` can be accessed.
+    fn phys_to_virt(&self, frame: Frame) -> *mut PageTable;
+}
+
+impl<F> PhysToVirt for F where F: Fn(Frame) -> *mut PageTable {
+    fn phys_to_virt(&self, frame: Frame) -> *mut PageTable {
+        self(frame)
+    }
+}
+
+/// A page table that accesses page table frames through a user supplied `PhysToVirt`.
+///
+/// This allows reusing one table walking implementation no matter how the kernel reaches its
+/// page table frames (identity mapping, linear mapping, temporary mapping window, ...).
+/// This struct implements the `Mapper` trait.
+pub struct MappedPageTable<'a, P: PhysToVirt> {
+    p2: &'a mut PageTable,
+    phys_to_virt: P,
+}
+
+impl<'a, P: PhysToVirt> MappedPageTable<'a, P> {
+    /// Creates a new MappedPageTable from the passed level 2 PageTable.
+    ///
+    /// `phys_to_virt` must return a valid pointer for every frame used as a page table,
+    /// including the ones allocated later.
+    pub unsafe fn new(p2: &'a mut PageTable, phys_to_virt: P) -> Self {
+        MappedPageTable { p2, phys_to_virt }
+    }
+
+    /// Returns the level 2 page table.
+    pub fn p2_table(&self) -> &PageTable {
+        self.p2
+    }
+
+    /// Returns the level 2 page table mutably.
+    pub fn p2_table_mut(&mut self) -> &mut PageTable {
+        self.p2
+    }
+
+    /// Returns the `PhysToVirt` used to access page table frames.
+    pub fn phys_to_virt(&self) -> &P {
+        &self.phys_to_virt
+    }
+
+    /// Runs `f` on the leaf entry of `page` in its p1 table. See `RecursivePageTable::get_entry`.
+    pub fn get_entry<F, T>(&self, page: Page, f: F) -> Option<T> where F: FnOnce(&PageTableEntry) -> T {
+        type F = PageTableFlags;
+        if !self.p2[page.p2_index()].flags().contains(F::VALID) || self.is_huge(page.p2_index()) {
+            return None;
+        }
+        Some(f(&self.p1(page.p2_index())[page.p1_index()]))
+    }
+
+    /// Runs `f` on the leaf entry of `page` in its p1 table, mutably.
+    /// See `RecursivePageTable::get_entry_mut`.
+    pub fn get_entry_mut<F, T>(&mut self, page: Page, f: F) -> Option<T> where F: FnOnce(&mut PageTableEntry) -> T {
+        type F = PageTableFlags;
+        if !self.p2[page.p2_index()].flags().contains(F::VALID) || self.is_huge(page.p2_index()) {
+            return None;
+        }
+        Some(f(&mut self.p1_mut(page.p2_index())[page.p1_index()]))
+    }
+
+    /// Summarizes the flags of all pages in `pages` in a single walk.
+    /// See `RecursivePageTable::range_flags_summary`.
+    pub fn range_flags_summary(&self, pages: PageRange) -> FlagsSummary {
+        type F = PageTableFlags;
+        let mut summary = FlagsSummary::new();
+        for page in pages {
+            let p2_entry = &self.p2[page.p2_index()];
+            let flags = if !p2_entry.flags().contains(F::VALID) {
+                F::empty()
+            } else if self.is_huge(page.p2_index()) {
+                p2_entry.flags()
+            } else {
+                self.p1(page.p2_index())[page.p1_index()].flags()
+            };
+            summary.add(flags);
+        }
+        summary
+    }
+
+    /// Counts the leaf entries and p1 tables of the page table. See `RecursivePageTable::stats`.
+    pub fn stats(&self) -> PageTableStats {
+        self.stats_p2(0, ENTRY_COUNT - 1, None)
+    }
+
+    /// Counts the mappings of pages in `pages` and the p1 tables covering them.
+    /// See `RecursivePageTable::stats_range`.
+    pub fn stats_range(&self, pages: PageRange) -> PageTableStats {
+        match p2_index_range(pages) {
+            Some((first, last)) => self.stats_p2(first, last, Some(pages)),
+            None => PageTableStats::default(),
+        }
+    }
+
+    fn stats_p2(&self, first: usize, last: usize, pages: Option<PageRange>) -> PageTableStats {
+        type F = PageTableFlags;
+        let mut stats = PageTableStats::default();
+        for i in first..=last {
+            if !self.p2[i].flags().contains(F::VALID) {
+                continue;
+            }
+            if self.is_huge(i) {
+                stats.pages_4m += 1;
+            } else {
+                stats.add_p1(i, self.p1(i), pages);
+            }
+        }
+        stats
+    }
+
+    /// Checks every valid entry for problems. See `RecursivePageTable::verify`.
+    pub fn verify(&self, phys_bits: u32, findings: &mut [Finding]) -> usize {
+        type F = PageTableFlags;
+        let mut verifier = Verifier::new(phys_bits, findings);
+        for i in 0..ENTRY_COUNT {
+            if !self.p2[i].flags().contains(F::VALID) {
+                continue;
+            }
+            if verifier.check_p2_entry(i, &self.p2[i]) {
+                verifier.check_p1(i, self.p1(i));
+            }
+        }
+        verifier.count()
+    }
+
+    /// Writes every valid entry to `writer`. See `RecursivePageTable::dump`.
+    pub fn dump<W: fmt::Write>(&self, writer: &mut W) -> fmt::Result {
+        type F = PageTableFlags;
+        for i in 0..ENTRY_COUNT {
+            let entry = &self.p2[i];
+            if !entry.flags().contains(F::VALID) {
+                continue;
+            }
+            dump_p2_entry(writer, i, entry, self.is_huge(i), None)?;
+            if !self.is_huge(i) {
+                dump_p1(writer, i, self.p1(i))?;
+            }
+        }
+        Ok(())
+    }
+
+    fn p1(&self, p2_index: usize) -> &PageTable {
+        unsafe { &*self.phys_to_virt.phys_to_virt(self.p2[p2_index].frame()) }
+    }
+
+    fn p1_mut(&mut self, p2_index: usize) -> &mut PageTable {
+        unsafe { &mut *self.phys_to_virt.phys_to_virt(self.p2[p2_index].frame()) }
+    }
+
+    unsafe fn clean_up_p1_tables<D>(&mut self, first: usize, last: usize, deallocator: &mut D) where D: FrameDeallocator {
+        use asm::sfence_vma_all;
+        type F = PageTableFlags;
+        for p2_index in first..=last {
+            if !self.p2[p2_index].flags().contains(F::VALID) || self.is_huge(p2_index)
+                || !self.p1(p2_index).is_empty() {
+                continue;
+            }
+            let frame = self.p2[p2_index].frame();
+            self.p2[p2_index].set_unused();
+            sfence_vma_all();
+            deallocator.dealloc(frame);
+        }
+    }
+
+    /// Maps `page` like `Mapper::map_to`, but marks the mapping and any newly created p1 table
+    /// as `GLOBAL`. See `RecursivePageTable::map_global`.
+    pub fn map_global<A>(&mut self, page: Page, frame: Frame, flags: PageTableFlags, allocator: &mut A) -> Result<MapperFlush, MapToError>
+        where A: FrameAllocator,
+    {
+        type F = PageTableFlags;
+        self.map_to_with_table_flags(page, frame, flags | F::GLOBAL, F::VALID | F::GLOBAL, allocator)
+    }
+
+    /// Maps the 4M page starting at `page` with a single p2 entry.
+    /// See `RecursivePageTable::map_to_4mib`.
+    pub fn map_to_4mib(&mut self, page: Page, frame: Frame, flags: PageTableFlags) -> Result<MapperFlush, MapToError> {
+        check_megapage(page, frame, flags)?;
+        if !self.p2[page.p2_index()].is_unused() {
+            return Err(MapToError::PageAlreadyMapped);
+        }
+        self.p2[page.p2_index()].set(frame, mark_soft_dirty(flags));
+        Ok(MapperFlush::new(page))
+    }
+
+    /// Removes the 4M mapping containing `page`. See `RecursivePageTable::unmap_4mib`.
+    pub fn unmap_4mib(&mut self, page: Page) -> Result<(Frame, MapperFlush), UnmapError> {
+        type F = PageTableFlags;
+        if !self.p2[page.p2_index()].flags().contains(F::VALID) || !self.is_huge(page.p2_index()) {
+            return Err(UnmapError::PageNotMapped);
+        }
+        let frame = self.p2[page.p2_index()].frame();
+        self.p2[page.p2_index()].set_unused();
+        Ok((frame, MapperFlush::new(page)))
+    }
+
+    fn is_huge(&self, p2_index: usize) -> bool {
+        type F = PageTableFlags;
+        self.p2[p2_index].flags().intersects(F::READABLE | F::WRITABLE | F::EXECUTABLE)
+    }
+
+    /// Creates the p1 table for `p2_index` with `table_flags` if it does not exist yet.
+    pub(crate) fn create_p1_if_not_exist<A>(&mut self, p2_index: usize, table_flags: PageTableFlags, allocator: &mut A) -> Result<(), MapToError>
+        where A: FrameAllocator,
+    {
+        type F = PageTableFlags;
+        if self.p2[p2_index].is_unused() {
+            let p1_frame = allocator.alloc().ok_or(MapToError::FrameAllocationFailed)?;
+            self.p2[p2_index].set(p1_frame, table_flags | F::VALID);
+            self.p1_mut(p2_index).zero();
+        } else if self.is_huge(p2_index) {
+            return Err(MapToError::ParentEntryHugePage);
+        }
+        Ok(())
+    }
+}
+
+impl<'a, P: PhysToVirt> Mapper for MappedPageTable<'a, P> {
+    fn map_to_with_table_flags<A>(&mut self, page: Page, frame: Frame, flags: PageTableFlags, table_flags: PageTableFlags, allocator: &mut A) -> Result<MapperFlush, MapToError>
+        where A: FrameAllocator,
+    {
+        check_table_flags(table_flags)?;
+        self.create_p1_if_not_exist(page.p2_index(), table_flags, allocator)?;
+        let p1 = self.p1_mut(page.p2_index());
+        if !p1[page.p1_index()].is_unused() {
+            return Err(MapToError::PageAlreadyMapped);
+        }
+        p1[page.p1_index()].set(frame, mark_soft_dirty(flags));
+        Ok(MapperFlush::new(page))
+    }
+
+    fn unmap(&mut self, page: Page) -> Result<(Frame, MapperFlush), UnmapError> {
+        type F = PageTableFlags;
+        if self.p2[page.p2_index()].is_unused() {
+            return Err(UnmapError::PageNotMapped);
+        }
+        if self.is_huge(page.p2_index()) {
+            return Err(UnmapError::ParentEntryHugePage);
+        }
+        let p1_entry = &mut self.p1_mut(page.p2_index())[page.p1_index()];
+        if !p1_entry.flags().contains(F::VALID) {
+            return Err(UnmapError::PageNotMapped);
+        }
+        let frame = p1_entry.frame();
+        p1_entry.set_unused();
+        Ok((frame, MapperFlush::new(page)))
+    }
+
+    fn update_flags(&mut self, page: Page, flags: PageTableFlags) -> Result<MapperFlush, FlagUpdateError> {
+        type F = PageTableFlags;
+        check_leaf_flags(flags)?;
+        if self.p2[page.p2_index()].is_unused() {
+            return Err(FlagUpdateError::PageNotMapped);
+        }
+        if self.is_huge(page.p2_index()) {
+            return Err(FlagUpdateError::ParentEntryHugePage);
+        }
+        let p1_entry = &mut self.p1_mut(page.p2_index())[page.p1_index()];
+        if !p1_entry.flags().contains(F::VALID) {
+            return Err(FlagUpdateError::PageNotMapped);
+        }
+        let frame = p1_entry.frame();
+        p1_entry.set(frame, mark_soft_dirty(flags));
+        Ok(MapperFlush::new(page))
+    }
+
+    fn translate_page(&self, page: Page) -> Option<Frame> {
+        let p2_entry = &self.p2[page.p2_index()];
+        if p2_entry.is_unused() {
+            return None;
+        }
+        if self.is_huge(page.p2_index()) {
+            let addr = p2_entry.addr().as_u32() + ((page.p1_index() as u32) << 12);
+            return Some(Frame::of_addr(PhysAddr::new(addr)));
+        }
+        let p1_entry = &self.p1(page.p2_index())[page.p1_index()];
+        if !p1_entry.flags().contains(PageTableFlags::VALID) {
+            return None;
+        }
+        Some(p1_entry.frame())
+    }
+}
+
+impl<'a, P: PhysToVirt> CleanUp for MappedPageTable<'a, P> {
+    unsafe fn clean_up<D>(&mut self, deallocator: &mut D) where D: FrameDeallocator {
+        self.clean_up_p1_tables(0, ENTRY_COUNT - 1, deallocator);
+    }
+
+    unsafe fn clean_up_range<D>(&mut self, pages: PageRange, deallocator: &mut D) where D: FrameDeallocator {
+        if let Some((first, last)) = p2_index_range(pages) {
+            self.clean_up_p1_tables(first, last, deallocator);
+        }
+    }
+}
+
+impl<'a, P: PhysToVirt> Translate for MappedPageTable<'a, P> {
+    fn translate(&self, addr: VirtAddr) -> TranslateResult {
+        type F = PageTableFlags;
+        let p2_entry = &self.p2[addr.p2_index()];
+        if !p2_entry.flags().contains(F::VALID) {
+            return TranslateResult::NotMapped;
+        }
+        if self.is_huge(addr.p2_index()) {
+            return translate_leaf(p2_entry, PageSize::Size4MiB, addr.as_usize() & 0x3fffff);
+        }
+        let p1_entry = &self.p1(addr.p2_index())[addr.p1_index()];
+        if !p1_entry.flags().contains(F::VALID) {
+            return TranslateResult::NotMapped;
+        }
+        translate_leaf(p1_entry, PageSize::Size4KiB, addr.page_offset())
+    }
+
+    fn region_mapped(&self, addr: VirtAddr) -> bool {
+        self.p2[addr.p2_index()].flags().contains(PageTableFlags::VALID)
+    }
+
+    fn leaf_entry(&self, page: Page) -> Option<PageTableEntry> {
+        self.get_entry(page, |entry| *entry)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const P1_FRAME: u32 = 0x8000_0000;
+
+    fn empty_table() -> PageTable {
+        let mut table: PageTable = unsafe { ::core::mem::zeroed() };
+        table.zero();
+        table
+    }
+
+    fn frame(addr: u32) -> Frame {
+        Frame::of_addr(PhysAddr::new(addr))
+    }
+
+    /// Builds the golden table image:
+    ///
+    /// - `p2[1]`: 4M leaf at `0x8040_0000`, `V R X`
+    /// - `p2[2]`: p1 table at `P1_FRAME`, with `p1[3]`: 4K leaf at `0x8020_5000`, `V R W`,
+    ///   and `p1[5]`: a pointer to a table at the last level
+    /// - `p2[3]`: 4M leaf at the misaligned `0x8000_1000`
+    fn golden_tables(p2: &mut PageTable, p1: &mut PageTable) {
+        type F = PageTableFlags;
+        p2[1].set(frame(0x8040_0000), F::VALID | F::READABLE | F::EXECUTABLE);
+        p2[2].set(frame(P1_FRAME), F::VALID);
+        p2[3].set(frame(0x8000_1000), F::VALID | F::READABLE);
+        p1[3].set(frame(0x8020_5000), F::VALID | F::READABLE | F::WRITABLE);
+        p1[5].set(frame(0x8020_6000), F::VALID);
+    }
+
+    #[test]
+    fn translate_golden_vectors() {
+        type F = PageTableFlags;
+        let mut p2 = empty_table();
+        let mut p1 = empty_table();
+        golden_tables(&mut p2, &mut p1);
+        let p1_ptr = &mut p1 as *mut PageTable;
+        let table = unsafe {
+            MappedPageTable::new(&mut p2, move |table_frame: Frame| {
+                assert_eq!(table_frame, frame(P1_FRAME), "walked to a frame that is no table");
+                p1_ptr
+            })
+        };
+
+        // (address, frame, offset, size, flags)
+        let mapped = [
+            (0x0040_0000, 0x8040_0000, 0x0, PageSize::Size4MiB, F::VALID | F::READABLE | F::EXECUTABLE),
+            (0x0040_1234, 0x8040_0000, 0x1234, PageSize::Size4MiB, F::VALID | F::READABLE | F::EXECUTABLE),
+            (0x007f_ffff, 0x8040_0000, 0x3f_ffff, PageSize::Size4MiB, F::VALID | F::READABLE | F::EXECUTABLE),
+            (0x0080_3000, 0x8020_5000, 0x0, PageSize::Size4KiB, F::VALID | F::READABLE | F::WRITABLE),
+            (0x0080_3abc, 0x8020_5000, 0xabc, PageSize::Size4KiB, F::VALID | F::READABLE | F::WRITABLE),
+        ];
+        for &(addr, expected_frame, expected_offset, expected_size, expected_flags) in mapped.iter() {
+            match table.translate(VirtAddr::new(addr)) {
+                TranslateResult::Mapped { frame: leaf, offset, flags, size } => {
+                    assert_eq!((leaf, offset, size, flags), (frame(expected_frame), expected_offset, expected_size, expected_flags), "{:#x}", addr);
+                }
+                other => panic!("{:#x} translated to {:?}", addr, other),
+            }
+            assert_eq!(table.translate_addr(VirtAddr::new(addr)), Some(PhysAddr::new(expected_frame + expected_offset as u32)));
+        }
+
+        assert_eq!(table.translate_page(Page::of_addr(VirtAddr::new(0x0040_1000))), Some(frame(0x8040_1000)));
+        assert_eq!(table.translate_page(Page::of_addr(VirtAddr::new(0x0080_3000))), Some(frame(0x8020_5000)));
+
+        for &addr in [0x0000_0000, 0x0080_4000, 0x0100_0000, 0xffff_f000].iter() {
+            match table.translate(VirtAddr::new(addr)) {
+                TranslateResult::NotMapped => {}
+                other => panic!("{:#x} translated to {:?}", addr, other),
+            }
+            assert_eq!(table.translate_page(Page::of_addr(VirtAddr::new(addr))), None);
+        }
+
+        // A table pointer at the last level and a misaligned megapage fault.
+        for &addr in [0x0080_5000, 0x00c0_0000].iter() {
+            match table.translate(VirtAddr::new(addr)) {
+                TranslateResult::InvalidEntry => {}
+                other => panic!("{:#x} translated to {:?}", addr, other),
+            }
+        }
+    }
+}
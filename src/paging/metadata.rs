@@ -0,0 +1,69 @@
+use core::ops::{Index, IndexMut};
+use addr::*;
+
+/// Per-frame metadata over a contiguous range of physical memory, stored in a caller provided
+/// slice with one slot per frame.
+///
+/// Lookups are a subtraction and an index, so this is meant as the shared backing structure
+/// of reference counts, reverse mappings, page state and the like.
+pub struct FrameMetadata<'a, T: 'a> {
+    slots: &'a mut [T],
+    start: usize,
+}
+
+impl<'a, T> FrameMetadata<'a, T> {
+    /// Creates a side table where `slots[i]` holds the metadata of the frame `start + i`.
+    pub fn new(slots: &'a mut [T], start: Frame) -> Self {
+        FrameMetadata { slots, start: start.number() }
+    }
+
+    /// Returns the frames covered by the table.
+    pub fn frames(&self) -> FrameRange {
+        let start = self.start as u32 * PAGE_SIZE as u32;
+        let end = start + (self.slots.len() * PAGE_SIZE) as u32;
+        FrameRange::new(Frame::of_addr(PhysAddr::new(start)), Frame::of_addr(PhysAddr::new(end)))
+    }
+
+    /// Returns whether the table has a slot for `frame`.
+    pub fn contains(&self, frame: Frame) -> bool {
+        self.slot(frame).is_some()
+    }
+
+    /// Returns the metadata of `frame`, or `None` if it lies outside the table.
+    pub fn get(&self, frame: Frame) -> Option<&T> {
+        let slot = self.slot(frame)?;
+        Some(&self.slots[slot])
+    }
+
+    /// Returns the metadata of `frame` mutably, or `None` if it lies outside the table.
+    pub fn get_mut(&mut self, frame: Frame) -> Option<&mut T> {
+        let slot = self.slot(frame)?;
+        Some(&mut self.slots[slot])
+    }
+
+    /// Sets the metadata of every frame to `value`.
+    pub fn fill(&mut self, value: T) where T: Clone {
+        for slot in self.slots.iter_mut() {
+            *slot = value.clone();
+        }
+    }
+
+    fn slot(&self, frame: Frame) -> Option<usize> {
+        let slot = frame.number().checked_sub(self.start)?;
+        if slot < self.slots.len() { Some(slot) } else { None }
+    }
+}
+
+impl<'a, T> Index<Frame> for FrameMetadata<'a, T> {
+    type Output = T;
+
+    fn index(&self, frame: Frame) -> &T {
+        self.get(frame).expect("frame outside of the metadata table")
+    }
+}
+
+impl<'a, T> IndexMut<Frame> for FrameMetadata<'a, T> {
+    fn index_mut(&mut self, frame: Frame) -> &mut T {
+        self.get_mut(frame).expect("frame outside of the metadata table")
+    }
+}
@@ -1,7 +1,54 @@
 mod page_table;
 mod recursive;
 mod frame_alloc;
+mod metadata;
+mod layout;
+mod sync;
+mod mapped;
+mod offset;
+mod shadow;
+mod heap;
+mod phys;
+mod walk;
+mod dump;
+mod clone;
+mod cow;
+mod journal;
+mod adopt;
+mod inactive;
+mod reserve;
+mod guard;
+mod kernel;
+mod verify;
+mod zero;
+#[cfg(feature = "soft-dirty")]
+mod soft_dirty;
+#[cfg(feature = "cfi")]
+mod shadow_stack;
 
 pub use self::page_table::*;
 pub use self::recursive::*;
-pub use self::frame_alloc::*;
\ No newline at end of file
+pub use self::frame_alloc::*;
+pub use self::metadata::*;
+pub use self::layout::*;
+pub use self::sync::*;
+pub use self::mapped::*;
+pub use self::offset::*;
+pub use self::shadow::*;
+pub use self::heap::*;
+pub use self::phys::*;
+pub use self::walk::*;
+pub use self::clone::*;
+pub use self::cow::*;
+pub use self::journal::*;
+pub use self::adopt::*;
+pub use self::inactive::*;
+pub use self::reserve::*;
+pub use self::guard::*;
+pub use self::kernel::*;
+pub use self::verify::Finding;
+pub use self::zero::*;
+#[cfg(feature = "soft-dirty")]
+pub use self::soft_dirty::*;
+#[cfg(feature = "cfi")]
+pub use self::shadow_stack::*;
\ No newline at end of file
@@ -0,0 +1,152 @@
+use core::fmt;
+use super::frame_alloc::*;
+use super::mapped::*;
+use super::page_table::*;
+use super::recursive::*;
+use super::verify::Finding;
+use super::walk::{FlagsSummary, PageTableStats};
+use addr::*;
+
+/// A `PhysToVirt` for physical memory that is mapped linearly at a fixed virtual offset.
+#[derive(Debug, Copy, Clone)]
+pub struct PhysOffset(pub usize);
+
+impl PhysToVirt for PhysOffset {
+    fn phys_to_virt(&self, frame: Frame) -> *mut PageTable {
+        (self.0 + frame.start_address().as_u32() as usize) as *mut PageTable
+    }
+}
+
+/// A page table that accesses all page table frames through a linear mapping of physical
+/// memory at a fixed virtual offset.
+///
+/// Unlike `RecursivePageTable`, no recursive entry is needed and no flags have to be flipped to
+/// reach the p1 tables. This struct implements the `Mapper` trait.
+pub struct OffsetPageTable<'a> {
+    inner: MappedPageTable<'a, PhysOffset>,
+}
+
+impl<'a> OffsetPageTable<'a> {
+    /// Creates a new OffsetPageTable from the passed level 2 PageTable.
+    ///
+    /// `phys_offset` is the virtual address at which physical address 0 is mapped, i.e. the
+    /// frame at physical address `p` must be accessible at virtual address `phys_offset + p`.
+    /// This must hold for all frames used as page tables, including the ones allocated later.
+    pub unsafe fn new(p2: &'a mut PageTable, phys_offset: usize) -> Self {
+        OffsetPageTable { inner: MappedPageTable::new(p2, PhysOffset(phys_offset)) }
+    }
+
+    /// Returns the virtual address at which physical address 0 is mapped.
+    pub fn phys_offset(&self) -> usize {
+        self.inner.phys_to_virt().0
+    }
+
+    /// Maps `page` like `Mapper::map_to`, but marks the mapping and any newly created p1 table
+    /// as `GLOBAL`. See `RecursivePageTable::map_global`.
+    pub fn map_global<A>(&mut self, page: Page, frame: Frame, flags: PageTableFlags, allocator: &mut A) -> Result<MapperFlush, MapToError>
+        where A: FrameAllocator,
+    {
+        self.inner.map_global(page, frame, flags, allocator)
+    }
+
+    /// Maps the 4M page starting at `page` with a single p2 entry.
+    /// See `RecursivePageTable::map_to_4mib`.
+    pub fn map_to_4mib(&mut self, page: Page, frame: Frame, flags: PageTableFlags) -> Result<MapperFlush, MapToError> {
+        self.inner.map_to_4mib(page, frame, flags)
+    }
+
+    /// Removes the 4M mapping containing `page`. See `RecursivePageTable::unmap_4mib`.
+    pub fn unmap_4mib(&mut self, page: Page) -> Result<(Frame, MapperFlush), UnmapError> {
+        self.inner.unmap_4mib(page)
+    }
+
+    /// Runs `f` on the leaf entry of `page` in its p1 table. See `RecursivePageTable::get_entry`.
+    pub fn get_entry<F, T>(&self, page: Page, f: F) -> Option<T> where F: FnOnce(&PageTableEntry) -> T {
+        self.inner.get_entry(page, f)
+    }
+
+    /// Runs `f` on the leaf entry of `page` in its p1 table, mutably.
+    /// See `RecursivePageTable::get_entry_mut`.
+    pub fn get_entry_mut<F, T>(&mut self, page: Page, f: F) -> Option<T> where F: FnOnce(&mut PageTableEntry) -> T {
+        self.inner.get_entry_mut(page, f)
+    }
+
+    /// Creates the p1 table for `p2_index` with `table_flags` if it does not exist yet.
+    pub(crate) fn create_p1_if_not_exist<A>(&mut self, p2_index: usize, table_flags: PageTableFlags, allocator: &mut A) -> Result<(), MapToError>
+        where A: FrameAllocator,
+    {
+        self.inner.create_p1_if_not_exist(p2_index, table_flags, allocator)
+    }
+
+    /// Summarizes the flags of all pages in `pages` in a single walk.
+    /// See `RecursivePageTable::range_flags_summary`.
+    pub fn range_flags_summary(&self, pages: PageRange) -> FlagsSummary {
+        self.inner.range_flags_summary(pages)
+    }
+
+    /// Counts the leaf entries and p1 tables of the page table. See `RecursivePageTable::stats`.
+    pub fn stats(&self) -> PageTableStats {
+        self.inner.stats()
+    }
+
+    /// Counts the mappings of pages in `pages` and the p1 tables covering them.
+    /// See `RecursivePageTable::stats_range`.
+    pub fn stats_range(&self, pages: PageRange) -> PageTableStats {
+        self.inner.stats_range(pages)
+    }
+
+    /// Checks every valid entry for problems. See `RecursivePageTable::verify`.
+    pub fn verify(&self, phys_bits: u32, findings: &mut [Finding]) -> usize {
+        self.inner.verify(phys_bits, findings)
+    }
+
+    /// Writes every valid entry to `writer`. See `RecursivePageTable::dump`.
+    pub fn dump<W: fmt::Write>(&self, writer: &mut W) -> fmt::Result {
+        self.inner.dump(writer)
+    }
+}
+
+impl<'a> Mapper for OffsetPageTable<'a> {
+    fn map_to_with_table_flags<A>(&mut self, page: Page, frame: Frame, flags: PageTableFlags, table_flags: PageTableFlags, allocator: &mut A) -> Result<MapperFlush, MapToError>
+        where A: FrameAllocator,
+    {
+        self.inner.map_to_with_table_flags(page, frame, flags, table_flags, allocator)
+    }
+
+    fn unmap(&mut self, page: Page) -> Result<(Frame, MapperFlush), UnmapError> {
+        self.inner.unmap(page)
+    }
+
+    fn update_flags(&mut self, page: Page, flags: PageTableFlags) -> Result<MapperFlush, FlagUpdateError> {
+        check_leaf_flags(flags)?;
+        self.inner.update_flags(page, flags)
+    }
+
+    fn translate_page(&self, page: Page) -> Option<Frame> {
+        self.inner.translate_page(page)
+    }
+}
+
+impl<'a> CleanUp for OffsetPageTable<'a> {
+    unsafe fn clean_up<D>(&mut self, deallocator: &mut D) where D: FrameDeallocator {
+        self.inner.clean_up(deallocator)
+    }
+
+    unsafe fn clean_up_range<D>(&mut self, pages: PageRange, deallocator: &mut D) where D: FrameDeallocator {
+        self.inner.clean_up_range(pages, deallocator)
+    }
+}
+
+impl<'a> Translate for OffsetPageTable<'a> {
+    fn translate(&self, addr: VirtAddr) -> TranslateResult {
+        self.inner.translate(addr)
+    }
+
+    fn region_mapped(&self, addr: VirtAddr) -> bool {
+        self.inner.region_mapped(addr)
+    }
+
+    fn leaf_entry(&self, page: Page) -> Option<PageTableEntry> {
+        self.inner.leaf_entry(page)
+    }
+}
@@ -1,6 +1,6 @@
 use addr::*;
 use core::ops::{Index, IndexMut};
-use core::fmt::{Debug, Formatter, Error};
+use core::fmt::{Debug, Display, Formatter, Error};
 
 pub struct PageTable {
     entries: [PageTableEntry; ENTRY_COUNT],
@@ -14,11 +14,24 @@ impl PageTable {
         }
     }
 
+    /// Returns whether all entries are unused.
+    pub fn is_empty(&self) -> bool {
+        self.entries.iter().all(|entry| entry.is_unused())
+    }
+
     /// Virtual address of root: (R, R+1, 0)
     pub fn set_recursive(&mut self, recursive_index: usize, frame: Frame) {
+        self.set_recursive_at(recursive_index, recursive_index + 1, frame);
+    }
+
+    /// Virtual address of root: (R, W, 0), where R is `recursive_index` and W is `rw_index`.
+    ///
+    /// The two indices can be placed independently, but must differ.
+    pub fn set_recursive_at(&mut self, recursive_index: usize, rw_index: usize, frame: Frame) {
         type EF = PageTableFlags;
+        assert_ne!(recursive_index, rw_index, "recursive and read-write index must differ");
         self[recursive_index].set(frame.clone(), EF::VALID);
-        self[recursive_index + 1].set(frame.clone(), EF::VALID | EF::READABLE | EF::WRITABLE);
+        self[rw_index].set(frame.clone(), EF::VALID | EF::READABLE | EF::WRITABLE);
     }
 
     /// Setup identity map: VirtPage at pagenumber -> PhysFrame at pagenumber
@@ -55,6 +68,14 @@ impl Debug for PageTable {
 pub struct PageTableEntry(u32);
 
 impl PageTableEntry {
+    pub(crate) fn new(frame: Frame, flags: PageTableFlags) -> Self {
+        let mut entry = PageTableEntry(0);
+        entry.set(frame, flags);
+        entry
+    }
+    pub(crate) fn unused() -> Self {
+        PageTableEntry(0)
+    }
     pub fn is_unused(&self) -> bool {
         self.0 == 0
     }
@@ -70,12 +91,130 @@ impl PageTableEntry {
     pub fn frame(&self) -> Frame {
         Frame::of_addr(self.addr())
     }
+    /// Returns the full 22 bit physical page number, which may lie above 4G.
+    pub(crate) fn ppn(&self) -> usize {
+        (self.0 >> 10) as usize
+    }
+    pub(crate) fn bits(&self) -> u32 {
+        self.0
+    }
+    /// Points the entry to `frame` with `flags`.
+    ///
+    /// Panics if the frame number doesn't fit the `PPN_BITS` wide PPN field.
     pub fn set(&mut self, frame: Frame, flags: PageTableFlags) {
-        self.0 = (frame.number() << 10) as u32 | flags.bits();
+        let ppn = frame.number();
+        assert!(ppn < 1 << PPN_BITS, "frame number does not fit the PPN field");
+        self.0 = (ppn << 10) as u32 | flags.bits();
+    }
+    /// Returns a formatter rendering the entry for humans, e.g. `PPN=0x80123 V R W A D` for a
+    /// leaf, `-> table @0x80200000` for a pointer to the next level or `unused`.
+    pub fn describe(&self) -> EntryDescription {
+        EntryDescription(*self)
     }
     pub fn flags_mut(&mut self) -> &mut PageTableFlags {
         unsafe { &mut *(self as *mut _ as *mut PageTableFlags) }
     }
+    /// Turns the entry into a swap entry holding `payload`, e.g. a swap slot number.
+    ///
+    /// A swap entry has VALID clear, so the hardware ignores all other bits. The layout is:
+    /// bit 0 (V) is 0, bit 1 is 1 to tell the entry apart from an unused one and bits 2..32
+    /// hold the payload. The payload must fit in `SWAP_PAYLOAD_BITS` bits.
+    pub fn set_swap(&mut self, payload: u32) {
+        assert!(payload < 1 << SWAP_PAYLOAD_BITS, "swap payload too large");
+        self.0 = payload << 2 | SWAP_MARKER;
+    }
+    /// Returns whether the entry is a swap entry written by `set_swap`.
+    pub fn is_swap(&self) -> bool {
+        self.0 & 0b11 == SWAP_MARKER
+    }
+    /// Returns the payload of a swap entry, or `None` if the entry isn't one.
+    pub fn swap_payload(&self) -> Option<u32> {
+        if self.is_swap() { Some(self.0 >> 2) } else { None }
+    }
+    /// Turns the entry into a reserved entry holding `tag`. See `reserve`.
+    ///
+    /// Like a swap entry, a reserved entry has VALID clear. Its layout is: bits 0 and 1 are 0,
+    /// bit 2 is 1 and bits 3..32 hold the tag. The tag must fit in `RESERVED_TAG_BITS` bits.
+    pub fn set_reserved(&mut self, tag: u32) {
+        assert!(tag < 1 << RESERVED_TAG_BITS, "reservation tag too large");
+        self.0 = tag << 3 | RESERVED_MARKER;
+    }
+    /// Returns whether the entry is a reserved entry written by `set_reserved`.
+    pub fn is_reserved(&self) -> bool {
+        self.0 & 0b111 == RESERVED_MARKER
+    }
+    /// Returns the tag of a reserved entry, or `None` if the entry isn't one.
+    pub fn reserved_tag(&self) -> Option<u32> {
+        if self.is_reserved() { Some(self.0 >> 3) } else { None }
+    }
+    /// Turns the entry into a guard entry. See `map_with_guard`.
+    ///
+    /// A guard entry has VALID clear and the low four bits `0b1000`; all other bits are 0.
+    pub fn set_guard(&mut self) {
+        self.0 = GUARD_ENTRY;
+    }
+    /// Returns whether the entry is a guard entry written by `set_guard`.
+    pub fn is_guard(&self) -> bool {
+        self.0 == GUARD_ENTRY
+    }
+}
+
+/// Width of the physical page number field of an entry.
+pub const PPN_BITS: u32 = 22;
+
+/// Number of payload bits a swap entry can hold. See `PageTableEntry::set_swap`.
+pub const SWAP_PAYLOAD_BITS: u32 = 30;
+
+const SWAP_MARKER: u32 = 0b10;
+
+/// Number of tag bits a reserved entry can hold. See `PageTableEntry::set_reserved`.
+pub const RESERVED_TAG_BITS: u32 = 29;
+
+const RESERVED_MARKER: u32 = 0b100;
+
+const GUARD_ENTRY: u32 = 0b1000;
+
+/// Human readable rendering of a `PageTableEntry`, returned by `PageTableEntry::describe`.
+pub struct EntryDescription(PageTableEntry);
+
+impl Display for EntryDescription {
+    fn fmt(&self, f: &mut Formatter) -> Result<(), Error> {
+        type F = PageTableFlags;
+        let entry = &self.0;
+        let flags = entry.flags();
+        if entry.is_unused() {
+            return f.write_str("unused");
+        }
+        if let Some(payload) = entry.swap_payload() {
+            return write!(f, "swap {:#x}", payload);
+        }
+        if let Some(tag) = entry.reserved_tag() {
+            return write!(f, "reserved {:#x}", tag);
+        }
+        if entry.is_guard() {
+            return f.write_str("guard");
+        }
+        if !flags.contains(F::VALID) {
+            return write!(f, "invalid {:#010x}", entry.0);
+        }
+        let leaf = flags.intersects(F::READABLE | F::WRITABLE | F::EXECUTABLE);
+        if leaf {
+            write!(f, "PPN={:#x}", entry.frame().number())?;
+        } else {
+            write!(f, "-> table @{:#010x}", entry.addr().as_u32())?;
+        }
+        let names = [
+            (F::VALID, "V"), (F::READABLE, "R"), (F::WRITABLE, "W"), (F::EXECUTABLE, "X"),
+            (F::USER, "U"), (F::GLOBAL, "G"), (F::ACCESSED, "A"), (F::DIRTY, "D"),
+            (F::RESERVED1, "SW1"), (F::RESERVED2, "SW2"),
+        ];
+        for &(flag, name) in names.iter() {
+            if flags.contains(flag) && (leaf || flag != F::VALID) {
+                write!(f, " {}", name)?;
+            }
+        }
+        Ok(())
+    }
 }
 
 impl Debug for PageTableEntry {
@@ -87,10 +226,13 @@ impl Debug for PageTableEntry {
     }
 }
 
-const ENTRY_COUNT: usize = 1 << 10;
+pub(crate) const ENTRY_COUNT: usize = 1 << 10;
 
 bitflags! {
     /// Possible flags for a page table entry.
+    ///
+    /// The software bits `RESERVED1` and `RESERVED2` of leaf entries are owned by this crate,
+    /// see `SoftwareState`.
     pub struct PageTableFlags: u32 {
         const VALID =       1 << 0;
         const READABLE =    1 << 1;
@@ -104,3 +246,137 @@ bitflags! {
         const RESERVED2 =   1 << 9;
     }
 }
+
+/// The use of the software bits `RESERVED1` (bit 8) and `RESERVED2` (bit 9) of a leaf entry.
+///
+/// This is the one place assigning these bits; every feature reads and writes them through
+/// `PageTableFlags::software_state` and `PageTableFlags::with_software_state`.
+///
+/// On a writable leaf, `RESERVED1` is clear and `RESERVED2` is the soft-dirty bit of the
+/// `soft-dirty` feature. On a leaf without `WRITABLE`, the two bits encode the state:
+///
+/// | Bit 8 | Bit 9 | State                | Owner                                          |
+/// |-------|-------|----------------------|------------------------------------------------|
+/// | 0     | 0     | `None`               |                                                |
+/// | 1     | 0     | `WriteProtected`     | `write_protect_all`, `write_enable_all`        |
+/// | 0     | 1     | `SoftDirtyProtected` | `clear_soft_dirty`, `resolve_soft_dirty_fault` |
+/// | 1     | 1     | `CopyOnWrite`        | `mark_cow`, `resolve_cow_fault`                |
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum SoftwareState {
+    /// The page is writable or was never write-protected by this crate.
+    None,
+    /// The page was writable before `RecursivePageTable::write_protect_all`.
+    WriteProtected,
+    /// The page was writable before `clear_soft_dirty` and has not been written since.
+    SoftDirtyProtected,
+    /// The page is a copy-on-write page, see `mark_cow`.
+    CopyOnWrite,
+}
+
+impl PageTableFlags {
+    /// Returns the state encoded in the software bits of a leaf entry. See `SoftwareState`.
+    pub fn software_state(&self) -> SoftwareState {
+        type F = PageTableFlags;
+        if self.contains(F::WRITABLE) {
+            return SoftwareState::None;
+        }
+        match (self.contains(F::RESERVED1), self.contains(F::RESERVED2)) {
+            (false, false) => SoftwareState::None,
+            (true, false) => SoftwareState::WriteProtected,
+            (false, true) => SoftwareState::SoftDirtyProtected,
+            (true, true) => SoftwareState::CopyOnWrite,
+        }
+    }
+
+    /// Returns the flags of a leaf without `WRITABLE` in `state`. See `SoftwareState`.
+    ///
+    /// `WRITABLE` and the previous software bits are removed.
+    pub fn with_software_state(&self, state: SoftwareState) -> Self {
+        type F = PageTableFlags;
+        let flags = *self - F::WRITABLE - F::RESERVED1 - F::RESERVED2;
+        match state {
+            SoftwareState::None => flags,
+            SoftwareState::WriteProtected => flags | F::RESERVED1,
+            SoftwareState::SoftDirtyProtected => flags | F::RESERVED2,
+            SoftwareState::CopyOnWrite => flags | F::RESERVED1 | F::RESERVED2,
+        }
+    }
+
+    /// Returns the flags of an execute-only page: `X` set, `R` and `W` clear.
+    ///
+    /// Loads from such a page fault unless `sstatus.MXR` is set; use `sstatus::with_mxr` to
+    /// read it, e.g. for instruction emulation.
+    pub fn execute_only(user: bool) -> Self {
+        type F = PageTableFlags;
+        let flags = F::VALID | F::EXECUTABLE;
+        if user { flags | F::USER } else { flags }
+    }
+
+    /// Returns whether these are the flags of an execute-only leaf.
+    pub fn is_execute_only(&self) -> bool {
+        type F = PageTableFlags;
+        self.contains(F::EXECUTABLE) && !self.intersects(F::READABLE | F::WRITABLE)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn swap_entry() {
+        let mut entry = PageTableEntry::unused();
+        entry.set_swap(0x1234);
+        assert_eq!(entry.bits(), 0x1234 << 2 | 0b10);
+        assert!(entry.is_swap() && !entry.is_reserved() && !entry.is_guard());
+        assert!(!entry.flags().contains(PageTableFlags::VALID));
+        assert_eq!(entry.swap_payload(), Some(0x1234));
+
+        entry.set_swap(0);
+        assert!(!entry.is_unused());
+        assert_eq!(entry.swap_payload(), Some(0));
+        entry.set_swap((1 << SWAP_PAYLOAD_BITS) - 1);
+        assert_eq!(entry.swap_payload(), Some((1 << SWAP_PAYLOAD_BITS) - 1));
+    }
+
+    #[test]
+    #[should_panic]
+    fn swap_payload_too_large() {
+        PageTableEntry::unused().set_swap(1 << SWAP_PAYLOAD_BITS);
+    }
+
+    #[test]
+    fn reserved_entry() {
+        let mut entry = PageTableEntry::unused();
+        entry.set_reserved(0x42);
+        assert_eq!(entry.bits(), 0x42 << 3 | 0b100);
+        assert!(entry.is_reserved() && !entry.is_swap() && !entry.is_guard());
+        assert_eq!(entry.reserved_tag(), Some(0x42));
+        assert_eq!(entry.swap_payload(), None);
+
+        entry.set_reserved(0);
+        assert!(!entry.is_unused());
+        assert_eq!(entry.reserved_tag(), Some(0));
+    }
+
+    #[test]
+    fn guard_entry() {
+        let mut entry = PageTableEntry::unused();
+        entry.set_guard();
+        assert_eq!(entry.bits(), 0b1000);
+        assert!(entry.is_guard() && !entry.is_swap() && !entry.is_reserved());
+        assert!(!entry.flags().contains(PageTableFlags::VALID));
+    }
+
+    #[test]
+    fn leaf_entry_is_no_software_entry() {
+        type F = PageTableFlags;
+        let frame = Frame::of_addr(PhysAddr::new(0x8020_5000));
+        let entry = PageTableEntry::new(frame, F::VALID | F::READABLE | F::WRITABLE);
+        assert_eq!(entry.bits(), 0x80205 << 10 | 0b111);
+        assert_eq!(entry.frame(), frame);
+        assert!(!entry.is_swap() && !entry.is_reserved() && !entry.is_guard());
+        assert!(PageTableEntry::unused().swap_payload().is_none());
+        assert!(PageTableEntry::unused().reserved_tag().is_none());
+    }
+}
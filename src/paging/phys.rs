@@ -0,0 +1,110 @@
+use core::ptr;
+use super::frame_alloc::*;
+use super::offset::*;
+use super::page_table::*;
+use super::recursive::*;
+use addr::*;
+
+/// A trait for types that can read physical memory.
+pub trait PhysRead {
+    /// Reads `buf.len()` bytes starting at physical address `addr` into `buf`.
+    fn read_phys(&mut self, addr: PhysAddr, buf: &mut [u8]);
+}
+
+/// A trait for types that can write physical memory.
+pub trait PhysWrite {
+    /// Writes `buf` to physical memory starting at physical address `addr`.
+    fn write_phys(&mut self, addr: PhysAddr, buf: &[u8]);
+
+    /// Fills `frame` with zeroes.
+    fn clear_frame(&mut self, frame: Frame) {
+        let buf = [0u8; 256];
+        for offset in (0..PAGE_SIZE as u32).step_by(buf.len()) {
+            self.write_phys(PhysAddr::new(frame.start_address().as_u32() + offset), &buf);
+        }
+    }
+}
+
+impl PhysRead for PhysOffset {
+    fn read_phys(&mut self, addr: PhysAddr, buf: &mut [u8]) {
+        let src = (self.0 + addr.as_u32() as usize) as *const u8;
+        unsafe { ptr::copy_nonoverlapping(src, buf.as_mut_ptr(), buf.len()); }
+    }
+}
+
+impl PhysWrite for PhysOffset {
+    fn write_phys(&mut self, addr: PhysAddr, buf: &[u8]) {
+        let dst = (self.0 + addr.as_u32() as usize) as *mut u8;
+        unsafe { ptr::copy_nonoverlapping(buf.as_ptr(), dst, buf.len()); }
+    }
+}
+
+/// Accesses physical memory by temporarily mapping each frame at a reserved page.
+///
+/// The mapper must manage the active page table.
+pub struct TemporaryMapping<'a, M: Mapper + 'a> {
+    mapper: &'a mut M,
+    page: Page,
+}
+
+struct NoFrames;
+
+impl FrameAllocator for NoFrames {
+    fn alloc(&mut self) -> Option<Frame> {
+        None
+    }
+}
+
+impl<'a, M: Mapper> TemporaryMapping<'a, M> {
+    /// Creates a new TemporaryMapping using the unmapped page `page` as its window.
+    ///
+    /// The p1 table for `page` is created up front, so later accesses never allocate.
+    pub fn new<A>(mapper: &'a mut M, page: Page, allocator: &mut A) -> Result<Self, MapToError>
+        where A: FrameAllocator,
+    {
+        mapper.map_to(page, Frame::of_addr(PhysAddr::new(0)), PageTableFlags::VALID, allocator)?.ignore();
+        mapper.unmap(page).expect("temporary page vanished").1.flush();
+        Ok(TemporaryMapping { mapper, page })
+    }
+
+    /// Maps `frame` at the window page, runs `f` on a pointer to it and unmaps it again.
+    pub fn with_frame<F, T>(&mut self, frame: Frame, f: F) -> T where F: FnOnce(*mut u8) -> T {
+        type F = PageTableFlags;
+        self.mapper.map_to(self.page, frame, F::VALID | F::READABLE | F::WRITABLE, &mut NoFrames)
+            .expect("failed to map temporary page").flush();
+        let ret = f(self.page.start_address().as_usize() as *mut u8);
+        self.mapper.unmap(self.page).expect("temporary page vanished").1.flush();
+        ret
+    }
+
+    /// Calls `f` with each frame touched by `[addr, addr + len)`, the offset into that frame,
+    /// the offset into the range and the chunk length.
+    fn for_each_chunk<F>(&mut self, addr: PhysAddr, len: usize, mut f: F) where F: FnMut(*mut u8, usize, usize) {
+        let mut done = 0;
+        while done < len {
+            let phys = addr.as_u32() as usize + done;
+            let offset = phys % PAGE_SIZE;
+            let chunk = (PAGE_SIZE - offset).min(len - done);
+            let frame = Frame::of_addr(PhysAddr::new(phys as u32));
+            self.with_frame(frame, |ptr| f(unsafe { ptr.add(offset) }, done, chunk));
+            done += chunk;
+        }
+    }
+}
+
+impl<'a, M: Mapper> PhysRead for TemporaryMapping<'a, M> {
+    fn read_phys(&mut self, addr: PhysAddr, buf: &mut [u8]) {
+        let len = buf.len();
+        self.for_each_chunk(addr, len, |src, done, chunk| unsafe {
+            ptr::copy_nonoverlapping(src, buf[done..].as_mut_ptr(), chunk);
+        });
+    }
+}
+
+impl<'a, M: Mapper> PhysWrite for TemporaryMapping<'a, M> {
+    fn write_phys(&mut self, addr: PhysAddr, buf: &[u8]) {
+        self.for_each_chunk(addr, buf.len(), |dst, done, chunk| unsafe {
+            ptr::copy_nonoverlapping(buf[done..].as_ptr(), dst, chunk);
+        });
+    }
+}
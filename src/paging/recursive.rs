@@ -1,5 +1,11 @@
+use core::fmt;
+use core::marker::PhantomData;
+use super::dump::*;
 use super::frame_alloc::*;
 use super::page_table::*;
+use super::phys::PhysWrite;
+use super::verify::*;
+use super::walk::{FlagsSummary, PageTableStats};
 use addr::*;
 
 pub trait Mapper {
@@ -8,6 +14,19 @@ pub trait Mapper {
     /// This function might need additional physical frames to create new page tables. These
     /// frames are allocated from the `allocator` argument. At most three frames are required.
     fn map_to<A>(&mut self, page: Page, frame: Frame, flags: PageTableFlags, allocator: &mut A) -> Result<MapperFlush, MapToError>
+        where A: FrameAllocator,
+    {
+        self.map_to_with_table_flags(page, frame, flags, PageTableFlags::VALID, allocator)
+    }
+
+    /// Creates a new mapping like `map_to`, but creates missing parent tables with
+    /// `table_flags` instead of bare `VALID`. Existing parent tables are left unchanged.
+    ///
+    /// Unlike on x86, only `GLOBAL` and the software bits are meaningful on non-leaf entries:
+    /// `USER`, `ACCESSED` and `DIRTY` are reserved, and any of `READABLE`, `WRITABLE` or
+    /// `EXECUTABLE` would turn the table into a leaf. Such flags are rejected with
+    /// `InvalidTableFlags`; user access is controlled by the `USER` flag of the leaf entry alone.
+    fn map_to_with_table_flags<A>(&mut self, page: Page, frame: Frame, flags: PageTableFlags, table_flags: PageTableFlags, allocator: &mut A) -> Result<MapperFlush, MapToError>
         where A: FrameAllocator;
 
     /// Removes a mapping from the page table and returns the frame that used to be mapped.
@@ -15,9 +34,72 @@ pub trait Mapper {
     /// Note that no page tables or pages are deallocated.
     fn unmap(&mut self, page: Page) -> Result<(Frame, MapperFlush), UnmapError>;
 
+    /// Updates the flags of an existing mapping, keeping the mapped frame.
+    ///
+    /// `flags` must describe a leaf, i.e. contain `VALID` and one of `READABLE`, `WRITABLE` or
+    /// `EXECUTABLE`, otherwise `InvalidLeafFlags` is returned.
+    fn update_flags(&mut self, page: Page, flags: PageTableFlags) -> Result<MapperFlush, FlagUpdateError>;
+
     /// Return the frame that the specified page is mapped to.
     fn translate_page(&self, page: Page) -> Option<Frame>;
 
+    /// Removes a mapping from the page table and returns its frame to `deallocator`.
+    ///
+    /// The page is flushed from the TLB before the frame is deallocated, as the frame may be
+    /// reused right away.
+    fn unmap_and_dealloc<D>(&mut self, page: Page, deallocator: &mut D) -> Result<(), UnmapError>
+        where D: FrameDeallocator,
+    {
+        let (frame, flush) = self.unmap(page)?;
+        flush.flush();
+        deallocator.dealloc(frame);
+        Ok(())
+    }
+
+    /// Starts a break-before-make removal of the mapping of `page`.
+    ///
+    /// The entry is invalidated and the page is flushed from the local TLB of all address
+    /// spaces right away. The frame is only handed out by `PendingUnmap::finish`, which the
+    /// caller should call once no other hart can still use a stale translation, e.g. after a
+    /// remote TLB shootdown.
+    fn begin_unmap(&mut self, page: Page) -> Result<PendingUnmap, UnmapError> {
+        let (frame, flush) = self.unmap(page)?;
+        flush.flush_global();
+        Ok(PendingUnmap { page, frame })
+    }
+
+    /// Return the physical address that the specified virtual address is mapped to.
+    fn translate_addr(&self, addr: VirtAddr) -> Option<PhysAddr> {
+        self.translate_page(Page::of_addr(addr))
+            .map(|frame| PhysAddr::new(frame.start_address().as_u32() + addr.page_offset() as u32))
+    }
+
+    /// Translates the virtual range `[addr, addr + len)` into physically contiguous segments.
+    ///
+    /// Consecutive pages mapped to consecutive frames are merged into one segment. The segments
+    /// are written to the start of `segments` and their number is returned, so a range that is
+    /// physically contiguous yields exactly one segment.
+    fn translate_range(&self, addr: VirtAddr, len: usize, segments: &mut [PhysRange]) -> Result<usize, TranslateRangeError> {
+        let mut count = 0;
+        let mut offset = 0;
+        while offset < len {
+            let virt = VirtAddr::new(addr.as_usize() + offset);
+            let chunk = (PAGE_SIZE - virt.page_offset()).min(len - offset);
+            let phys = self.translate_addr(virt).ok_or(TranslateRangeError::NotMapped(virt))?;
+            if count > 0 && segments[count - 1].end() == Some(phys) {
+                segments[count - 1].len += chunk;
+            } else {
+                if count == segments.len() {
+                    return Err(TranslateRangeError::TooManySegments);
+                }
+                segments[count] = PhysRange::new(phys, chunk);
+                count += 1;
+            }
+            offset += chunk;
+        }
+        Ok(count)
+    }
+
     /// Maps the given frame to the virtual page with the same address.
     fn identity_map<A>(&mut self, frame: Frame, flags: PageTableFlags, allocator: &mut A) -> Result<MapperFlush, MapToError>
         where A: FrameAllocator,
@@ -25,6 +107,194 @@ pub trait Mapper {
         let page = Page::of_addr(VirtAddr::new(frame.start_address().as_u32() as usize));
         self.map_to(page, frame, flags, allocator)
     }
+
+    /// Maps every page in `pages` to the corresponding frame in `frames`.
+    ///
+    /// The two ranges must have the same length, otherwise `RangeLengthMismatch` is returned
+    /// and nothing is mapped. Instead of one flush per page, a single `MapperFlushAll` is
+    /// returned. If an error occurs, the pages mapped so far stay mapped.
+    fn map_range<A>(&mut self, pages: PageRange, frames: FrameRange, flags: PageTableFlags, allocator: &mut A) -> Result<MapperFlushAll, MapToError>
+        where A: FrameAllocator,
+    {
+        if pages.len() != frames.len() {
+            return Err(MapToError::RangeLengthMismatch);
+        }
+        for (page, frame) in pages.zip(frames) {
+            self.map_to(page, frame, flags, allocator)?.ignore();
+        }
+        Ok(MapperFlushAll::new())
+    }
+
+    /// Maps every page in `pages` to a newly allocated frame, which is zeroed through `phys`,
+    /// i.e. allocates anonymous memory.
+    ///
+    /// Instead of one flush per page, a single `MapperFlushAll` is returned. If an error
+    /// occurs, the frame allocated for the failing page is deallocated again and the pages
+    /// mapped so far stay mapped.
+    fn map_range_alloc<P, A>(&mut self, pages: PageRange, flags: PageTableFlags, phys: &mut P, allocator: &mut A) -> Result<MapperFlushAll, MapToError>
+        where P: PhysWrite, A: FrameAllocator + FrameDeallocator,
+    {
+        for page in pages {
+            let frame = allocator.alloc().ok_or(MapToError::FrameAllocationFailed)?;
+            phys.clear_frame(frame);
+            match self.map_to(page, frame, flags, allocator) {
+                Ok(flush) => flush.ignore(),
+                Err(err) => {
+                    allocator.dealloc(frame);
+                    return Err(err);
+                }
+            }
+        }
+        Ok(MapperFlushAll::new())
+    }
+
+    /// Removes the mappings of all pages in `pages`, calling `f` with each page and the frame
+    /// it was mapped to.
+    ///
+    /// Pages that are not mapped are skipped. A single `MapperFlushAll` is returned. Note that
+    /// no page tables or pages are deallocated.
+    fn unmap_range<F>(&mut self, pages: PageRange, mut f: F) -> Result<MapperFlushAll, UnmapError>
+        where F: FnMut(Page, Frame),
+    {
+        for page in pages {
+            match self.unmap(page) {
+                Ok((frame, flush)) => {
+                    flush.ignore();
+                    f(page, frame);
+                }
+                Err(UnmapError::PageNotMapped) => {}
+                Err(err) => return Err(err),
+            }
+        }
+        Ok(MapperFlushAll::new())
+    }
+}
+
+/// A trait for page tables that can report how an address is mapped.
+pub trait Translate {
+    /// Returns the frame, flags, page size and offset of the mapping of `addr`.
+    fn translate(&self, addr: VirtAddr) -> TranslateResult;
+
+    /// Returns whether any page of the 4M region containing `addr` may be mapped, i.e. whether
+    /// its p2 entry is valid.
+    ///
+    /// Walks use this to skip unmapped regions. The default implementation returns `true`.
+    fn region_mapped(&self, _addr: VirtAddr) -> bool {
+        true
+    }
+
+    /// Returns the 4K leaf entry of `page`, or `None` if there is no p1 table for it.
+    ///
+    /// Unlike `translate`, this also returns non-present entries, e.g. swap entries. The
+    /// default implementation returns `None`.
+    fn leaf_entry(&self, _page: Page) -> Option<PageTableEntry> {
+        None
+    }
+}
+
+/// The size of a leaf mapping.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum PageSize {
+    /// A page mapped by a p1 entry.
+    Size4KiB,
+    /// A megapage mapped directly by a p2 entry.
+    Size4MiB,
+}
+
+impl PageSize {
+    /// Returns the size in bytes.
+    pub fn bytes(&self) -> usize {
+        match *self {
+            PageSize::Size4KiB => 1 << 12,
+            PageSize::Size4MiB => 1 << 22,
+        }
+    }
+}
+
+/// The result of a `Translate::translate` call.
+#[derive(Debug, Copy, Clone)]
+pub enum TranslateResult {
+    /// The address is mapped.
+    Mapped {
+        /// The first frame of the leaf mapping.
+        frame: Frame,
+        /// The offset of the address inside the leaf mapping.
+        offset: usize,
+        /// The flags of the leaf entry.
+        flags: PageTableFlags,
+        /// The size of the leaf mapping.
+        size: PageSize,
+    },
+    /// No valid entry maps the address.
+    NotMapped,
+    /// The leaf entry is valid but malformed: it is a pointer to a table at the last level,
+    /// uses the reserved `WRITABLE`-without-`READABLE` encoding or is a misaligned megapage.
+    /// With the `cfi` feature, `WRITABLE`-without-`READABLE` is a valid shadow stack page.
+    InvalidEntry,
+}
+
+impl TranslateResult {
+    /// Returns the physical address for a `Mapped` result.
+    pub fn phys_addr(&self) -> Option<PhysAddr> {
+        match *self {
+            TranslateResult::Mapped { frame, offset, .. } =>
+                Some(PhysAddr::new(frame.start_address().as_u32() + offset as u32)),
+            _ => None,
+        }
+    }
+}
+
+/// Classifies a valid leaf entry for `Translate` implementations.
+///
+/// Execute-only leaves (`X` without `R`) are valid mappings, see `PageTableFlags::execute_only`.
+pub(crate) fn translate_leaf(entry: &PageTableEntry, size: PageSize, offset: usize) -> TranslateResult {
+    type F = PageTableFlags;
+    let flags = entry.flags();
+    if !flags.intersects(F::READABLE | F::WRITABLE | F::EXECUTABLE)
+        || (flags.contains(F::WRITABLE) && !flags.contains(F::READABLE) && !cfg!(feature = "cfi"))
+        || (size == PageSize::Size4MiB && entry.frame().p1_index() != 0)
+    {
+        return TranslateResult::InvalidEntry;
+    }
+    TranslateResult::Mapped { frame: entry.frame(), offset, flags, size }
+}
+
+/// A trait for page tables that can free their empty intermediate tables.
+pub trait CleanUp {
+    /// Frees all p1 tables that contain no entries and clears the p2 entries pointing to them.
+    ///
+    /// The TLB is flushed before each table is handed to `deallocator`.
+    ///
+    /// ## Safety
+    ///
+    /// The p1 tables must not be shared with other page tables or referenced from elsewhere.
+    unsafe fn clean_up<D>(&mut self, deallocator: &mut D) where D: FrameDeallocator;
+
+    /// Like `clean_up`, but only considers the p1 tables mapping pages in `pages`.
+    unsafe fn clean_up_range<D>(&mut self, pages: PageRange, deallocator: &mut D) where D: FrameDeallocator;
+}
+
+/// Returns the range of p2 indices `[first, last]` covered by `pages`.
+pub(crate) fn p2_index_range(pages: PageRange) -> Option<(usize, usize)> {
+    if pages.is_empty() {
+        return None;
+    }
+    let last = Page::of_addr(VirtAddr::new(pages.end.start_address().as_usize().wrapping_sub(PAGE_SIZE)));
+    Some((pages.start.p2_index(), last.p2_index()))
+}
+
+/// Makes other harts flush their TLBs, e.g. by sending them inter-processor interrupts.
+///
+/// `sfence.vma` only affects the local hart, so after changing mappings that other harts may
+/// use, their TLBs have to be flushed too. The crate can't do this on its own; the kernel
+/// implements this trait with its IPI mechanism and passes it to the `flush_with` methods.
+/// Implementations must not return before the other harts have flushed.
+pub trait TlbShootdown {
+    /// Makes all other harts flush `page` from the TLBs of all address spaces.
+    fn shootdown_page(&self, page: Page);
+
+    /// Makes all other harts flush their whole TLB.
+    fn shootdown_all(&self);
 }
 
 #[must_use = "Page Table changes must be flushed or ignored."]
@@ -32,14 +302,180 @@ pub struct MapperFlush(Page);
 
 impl MapperFlush {
     /// Create a new flush promise
-    fn new(page: Page) -> Self {
+    pub(crate) fn new(page: Page) -> Self {
         MapperFlush(page)
     }
 
     /// Flush the page from the TLB to ensure that the newest mapping is used.
+    ///
+    /// The page is flushed in all address spaces, see `flush_with_asid` for a scoped flush.
     pub fn flush(self) {
+        use asm::sfence_vma_addr;
+        sfence_vma_addr(self.0.start_address());
+    }
+
+    /// Flush the page from the TLB of the address space `asid`.
+    pub fn flush_with_asid(self, asid: usize) {
         use asm::sfence_vma;
-        sfence_vma(0, self.0.start_address());
+        sfence_vma(asid, self.0.start_address());
+    }
+
+    /// Flush the page from the TLB of all address spaces.
+    ///
+    /// Same as `flush`; use this for `GLOBAL` mappings to make the intent explicit.
+    pub fn flush_global(self) {
+        use asm::sfence_vma_addr;
+        sfence_vma_addr(self.0.start_address());
+    }
+
+    /// Flush the page from the TLBs of all address spaces on this and all other harts.
+    pub fn flush_with<S: TlbShootdown>(self, shootdown: &S) {
+        use asm::sfence_vma_addr;
+        sfence_vma_addr(self.0.start_address());
+        shootdown.shootdown_page(self.0);
+    }
+
+    /// Don't flush the TLB and silence the “must be used” warning.
+    pub fn ignore(self) {}
+}
+
+/// A flush promise for changes to many pages, which are flushed together by flushing the whole
+/// TLB.
+#[must_use = "Page Table changes must be flushed or ignored."]
+pub struct MapperFlushAll(());
+
+impl MapperFlushAll {
+    /// Create a new flush promise
+    pub(crate) fn new() -> Self {
+        MapperFlushAll(())
+    }
+
+    /// Flush the whole TLB to ensure that the newest mappings are used.
+    pub fn flush_all(self) {
+        use asm::sfence_vma_all;
+        sfence_vma_all();
+    }
+
+    /// Flush all non-global translations of the address space `asid` from the TLB.
+    pub fn flush_all_asid(self, asid: usize) {
+        use asm::sfence_vma_asid;
+        sfence_vma_asid(asid);
+    }
+
+    /// Flush the whole TLB on this and all other harts.
+    pub fn flush_with<S: TlbShootdown>(self, shootdown: &S) {
+        use asm::sfence_vma_all;
+        sfence_vma_all();
+        shootdown.shootdown_all();
+    }
+
+    /// Don't flush the TLB and silence the “must be used” warning.
+    pub fn ignore(self) {}
+}
+
+/// A mapping that was invalidated by `Mapper::begin_unmap`, but whose frame is still held
+/// back because other harts may access it through stale TLB entries.
+#[must_use = "the frame is only released by `finish`"]
+#[derive(Debug)]
+pub struct PendingUnmap {
+    page: Page,
+    frame: Frame,
+}
+
+impl PendingUnmap {
+    /// Returns the page that was unmapped, e.g. to send a remote TLB shootdown for it.
+    pub fn page(&self) -> Page {
+        self.page
+    }
+
+    /// Completes the unmap and returns the frame, which may be reused from now on.
+    ///
+    /// Call this only after all other harts have flushed the page from their TLBs.
+    pub fn finish(self) -> Frame {
+        self.frame
+    }
+}
+
+const FLUSH_BATCH_CAPACITY: usize = 32;
+
+/// Collects the flush promises of many page table changes and flushes them at once.
+///
+/// Up to `threshold` pages are flushed one by one; if more pages are pushed, or a
+/// `MapperFlushAll` is pushed, the whole TLB is flushed instead.
+#[must_use = "Page Table changes must be flushed or ignored."]
+pub struct FlushBatch {
+    pages: [Page; FLUSH_BATCH_CAPACITY],
+    len: usize,
+    threshold: usize,
+    all: bool,
+}
+
+impl FlushBatch {
+    /// Creates an empty batch with the default threshold of 32 pages.
+    pub fn new() -> Self {
+        Self::with_threshold(FLUSH_BATCH_CAPACITY)
+    }
+
+    /// Creates an empty batch which flushes the whole TLB once more than `threshold` pages
+    /// were pushed. The threshold is capped at 32.
+    pub fn with_threshold(threshold: usize) -> Self {
+        FlushBatch {
+            pages: [Page::of_addr(VirtAddr::new(0)); FLUSH_BATCH_CAPACITY],
+            len: 0,
+            threshold: threshold.min(FLUSH_BATCH_CAPACITY),
+            all: false,
+        }
+    }
+
+    /// Adds the page of `flush` to the batch.
+    pub fn push(&mut self, flush: MapperFlush) {
+        if self.all {
+            return;
+        }
+        if self.len == self.threshold {
+            self.all = true;
+            return;
+        }
+        self.pages[self.len] = flush.0;
+        self.len += 1;
+    }
+
+    /// Adds a bulk change to the batch, which will flush the whole TLB.
+    pub fn push_all(&mut self, flush: MapperFlushAll) {
+        flush.ignore();
+        self.all = true;
+    }
+
+    /// Returns true if nothing was pushed yet.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0 && !self.all
+    }
+
+    /// Flushes all pushed pages from the TLB.
+    pub fn flush(self) {
+        use asm::{sfence_vma_addr, sfence_vma_all};
+        if self.all {
+            sfence_vma_all();
+        } else {
+            for page in &self.pages[..self.len] {
+                sfence_vma_addr(page.start_address());
+            }
+        }
+    }
+
+    /// Flushes all pushed pages from the TLBs of all address spaces on this and all other
+    /// harts. Other harts are asked only once for the whole batch if it exceeds the threshold.
+    pub fn flush_with<S: TlbShootdown>(self, shootdown: &S) {
+        if self.is_empty() {
+            return;
+        }
+        if self.all {
+            MapperFlushAll::new().flush_with(shootdown);
+        } else {
+            for &page in &self.pages[..self.len] {
+                MapperFlush::new(page).flush_with(shootdown);
+            }
+        }
     }
 
     /// Don't flush the TLB and silence the “must be used” warning.
@@ -57,6 +493,62 @@ pub enum MapToError {
     ParentEntryHugePage,
     /// The given page is already mapped to a physical frame.
     PageAlreadyMapped,
+    /// The given page lies in the region reserved for the recursive mapping.
+    ReservedIndex,
+    /// The flags for new parent tables contain flags that are not allowed on non-leaf entries.
+    InvalidTableFlags,
+    /// The page or frame passed to `map_to_4mib` is not 4M aligned.
+    MisalignedMegapage,
+    /// The flags are not valid for the new mapping.
+    InvalidLeafFlags,
+    /// The pages to map would extend beyond the address space.
+    AddressOverflow,
+    /// The shared zero page is not mapped in the page table, see `zero_frame`.
+    ZeroPageNotMapped,
+    /// The page and frame ranges passed to `map_range` differ in length.
+    RangeLengthMismatch,
+}
+
+/// Checks the arguments of `map_to_4mib`.
+pub(crate) fn check_megapage(page: Page, frame: Frame, flags: PageTableFlags) -> Result<(), MapToError> {
+    type F = PageTableFlags;
+    if !flags.intersects(F::READABLE | F::WRITABLE | F::EXECUTABLE) {
+        return Err(MapToError::InvalidLeafFlags);
+    }
+    if page.p1_index() != 0 || frame.p1_index() != 0 {
+        return Err(MapToError::MisalignedMegapage);
+    }
+    Ok(())
+}
+
+/// Checks the flags passed to `Mapper::update_flags`.
+pub(crate) fn check_leaf_flags(flags: PageTableFlags) -> Result<(), FlagUpdateError> {
+    type F = PageTableFlags;
+    if !flags.contains(F::VALID) || !flags.intersects(F::READABLE | F::WRITABLE | F::EXECUTABLE) {
+        return Err(FlagUpdateError::InvalidLeafFlags);
+    }
+    Ok(())
+}
+
+/// Checks the flags passed to `Mapper::map_to_with_table_flags` for new parent tables.
+pub(crate) fn check_table_flags(table_flags: PageTableFlags) -> Result<(), MapToError> {
+    type F = PageTableFlags;
+    let forbidden = F::READABLE | F::WRITABLE | F::EXECUTABLE | F::USER | F::ACCESSED | F::DIRTY;
+    if table_flags.intersects(forbidden) {
+        return Err(MapToError::InvalidTableFlags);
+    }
+    Ok(())
+}
+
+/// Adds the soft-dirty bit `RESERVED2` to the flags of a writable mapping, if the
+/// `soft-dirty` feature is enabled.
+pub(crate) fn mark_soft_dirty(flags: PageTableFlags) -> PageTableFlags {
+    type F = PageTableFlags;
+    if cfg!(feature = "soft-dirty") && flags.contains(F::WRITABLE) {
+        flags | F::RESERVED2
+    } else {
+        flags
+    }
 }
 
 /// An error indicating that an `unmap` call failed.
@@ -69,16 +561,49 @@ pub enum UnmapError {
     PageNotMapped,
     /// The page table entry for the given page points to an invalid physical address.
     InvalidFrameAddress(PhysAddr),
+    /// The given page lies in the region reserved for the recursive mapping.
+    ReservedIndex,
+}
+
+/// An error indicating that a `translate_range` call failed.
+#[derive(Debug)]
+pub enum TranslateRangeError {
+    /// The given address is not mapped.
+    NotMapped(VirtAddr),
+    /// The range consists of more physical segments than fit into the given slice.
+    TooManySegments,
+}
+
+/// An error indicating that an `update_flags` call failed.
+#[derive(Debug)]
+pub enum FlagUpdateError {
+    /// The given page is not mapped to a physical frame.
+    PageNotMapped,
+    /// An upper level page table entry is a 4M page, which means that the given page is part
+    /// of a huge page and its flags can't be changed individually.
+    ParentEntryHugePage,
+    /// The given page lies in the region reserved for the recursive mapping.
+    ReservedIndex,
+    /// The new flags are not valid for a leaf entry.
+    InvalidLeafFlags,
 }
 
 /// A recursive page table is a last level page table with an entry mapped to the table itself.
 ///
 /// This struct implements the `Mapper` trait.
 pub struct RecursivePageTable<'a> {
-    p2: &'a mut PageTable,
+    /// Held as a raw pointer because reading a p1 table through the recursive mapping needs
+    /// to flip the flags of a p2 entry, see `read_p1`.
+    p2: *mut PageTable,
     recursive_index: usize,
+    rw_index: usize,
+    _marker: PhantomData<&'a mut PageTable>,
 }
 
+/// The p2 table is exclusively borrowed for `'a`, so the page table can be moved to another
+/// hart. It is not `Sync`: `read_p1` mutates p2 entries through `&self`.
+unsafe impl<'a> Send for RecursivePageTable<'a> {}
+
 /// An error indicating that the given page table is not recursively mapped.
 ///
 /// Returned from `RecursivePageTable::new`.
@@ -90,23 +615,27 @@ impl<'a> RecursivePageTable<'a> {
     ///
     /// The page table must be recursively mapped, that means:
     ///
-    /// - The page table must have one recursive entry, i.e. an entry that points to the table
-    ///   itself.
+    /// - The page table must have one recursive entry, i.e. a non-leaf entry that points to the
+    ///   table itself, and one read-write entry, i.e. a `VALID | READABLE | WRITABLE` leaf entry
+    ///   that points to the table itself. See `PageTable::set_recursive_at`.
+    /// - The page table must be accessed at `(recursive_index, rw_index, 0)`, which determines
+    ///   both indices.
     /// - The page table must be active, i.e. the satp register must contain its physical address.
     ///
     /// Otherwise `Err(NotRecursivelyMapped)` is returned.
     pub fn new(table: &'a mut PageTable) -> Result<Self, NotRecursivelyMapped> {
         let page = Page::of_addr(VirtAddr::new(table as *const _ as usize));
         let recursive_index = page.p2_index();
+        let rw_index = page.p1_index();
 
         use register::satp;
         type F = PageTableFlags;
-        if page.p1_index() != recursive_index + 1
+        if rw_index == recursive_index
             || satp::read().frame() != table[recursive_index].frame()
-            || satp::read().frame() != table[recursive_index + 1].frame()
+            || satp::read().frame() != table[rw_index].frame()
             || !table[recursive_index].flags().contains(F::VALID)
-            ||  table[recursive_index].flags().contains(F::READABLE | F::WRITABLE)
-            || !table[recursive_index + 1].flags().contains(F::VALID | F::READABLE | F::WRITABLE)
+            ||  table[recursive_index].flags().intersects(F::READABLE | F::WRITABLE | F::EXECUTABLE)
+            || !table[rw_index].flags().contains(F::VALID | F::READABLE | F::WRITABLE)
         {
             return Err(NotRecursivelyMapped);
         }
@@ -114,72 +643,445 @@ impl<'a> RecursivePageTable<'a> {
         Ok(RecursivePageTable {
             p2: table,
             recursive_index,
+            rw_index,
+            _marker: PhantomData,
         })
     }
 
     /// Creates a new RecursivePageTable without performing any checks.
     ///
-    /// The `recursive_index` parameter must be the index of the recursively mapped entry.
+    /// The `recursive_index` parameter must be the index of the recursively mapped entry, the
+    /// read-write entry must be the next one.
     pub unsafe fn new_unchecked(table: &'a mut PageTable, recursive_index: usize) -> Self {
+        Self::new_unchecked_at(table, recursive_index, recursive_index + 1)
+    }
+
+    /// Creates a new RecursivePageTable with the given recursive and read-write entries,
+    /// without performing any checks.
+    pub unsafe fn new_unchecked_at(table: &'a mut PageTable, recursive_index: usize, rw_index: usize) -> Self {
         RecursivePageTable {
             p2: table,
             recursive_index,
+            rw_index,
+            _marker: PhantomData,
+        }
+    }
+
+    /// Returns the indices of the recursive entry and the read-write self entry.
+    pub fn recursive_indices(&self) -> (usize, usize) {
+        (self.recursive_index, self.rw_index)
+    }
+
+    /// Returns the level 2 page table.
+    pub fn p2_table(&self) -> &PageTable {
+        unsafe { &*self.p2 }
+    }
+
+    /// Returns the level 2 page table mutably.
+    pub fn p2_table_mut(&mut self) -> &mut PageTable {
+        unsafe { &mut *self.p2 }
+    }
+
+    /// Runs `f` on the p1 table referenced by entry `p2_index` of the p2 table.
+    ///
+    /// The p1 table is only accessible while `f` runs. Returns `None` if the entry does not
+    /// point to a p1 table, i.e. it is unused, a 4M page or a recursive entry.
+    pub fn p1_table<F, T>(&self, p2_index: usize, f: F) -> Option<T> where F: FnOnce(&PageTable) -> T {
+        if !self.is_p1_table(p2_index) {
+            return None;
+        }
+        Some(self.read_p1(p2_index, f))
+    }
+
+    /// Runs `f` on the p1 table referenced by entry `p2_index` of the p2 table, mutably.
+    ///
+    /// See `p1_table`.
+    pub fn p1_table_mut<F, T>(&mut self, p2_index: usize, f: F) -> Option<T> where F: FnOnce(&mut PageTable) -> T {
+        if !self.is_p1_table(p2_index) {
+            return None;
+        }
+        Some(self.edit_p1(p2_index, f))
+    }
+
+    /// Runs `f` on the leaf entry of `page` in its p1 table.
+    ///
+    /// The entry may be unused. Returns `None` if there is no p1 table for `page`, i.e. its p2
+    /// entry is unused, a 4M page or a recursive entry.
+    pub fn get_entry<F, T>(&self, page: Page, f: F) -> Option<T> where F: FnOnce(&PageTableEntry) -> T {
+        self.p1_table(page.p2_index(), |p1| f(&p1[page.p1_index()]))
+    }
+
+    /// Runs `f` on the leaf entry of `page` in its p1 table, mutably. See `get_entry`.
+    ///
+    /// Changes made by `f` must be flushed from the TLB by the caller.
+    pub fn get_entry_mut<F, T>(&mut self, page: Page, f: F) -> Option<T> where F: FnOnce(&mut PageTableEntry) -> T {
+        self.p1_table_mut(page.p2_index(), |p1| f(&mut p1[page.p1_index()]))
+    }
+
+    /// Summarizes the flags of all pages in `pages` in a single walk.
+    ///
+    /// Pages in 4M mappings get the flags of the 4M entry. The recursive window counts as
+    /// unmapped.
+    pub fn range_flags_summary(&self, pages: PageRange) -> FlagsSummary {
+        let mut summary = FlagsSummary::new();
+        let mut rest = pages;
+        while !rest.is_empty() {
+            let p2_index = rest.start.p2_index();
+            let walked = self.p1_table(p2_index, |p1| {
+                while !rest.is_empty() && rest.start.p2_index() == p2_index {
+                    let page = rest.next().unwrap();
+                    summary.add(p1[page.p1_index()].flags());
+                }
+            });
+            if walked.is_none() {
+                let flags = if self.is_huge(p2_index) && !self.is_recursive_index(p2_index) {
+                    self.p2_table()[p2_index].flags()
+                } else {
+                    PageTableFlags::empty()
+                };
+                while !rest.is_empty() && rest.start.p2_index() == p2_index {
+                    rest.next();
+                    summary.add(flags);
+                }
+            }
+        }
+        summary
+    }
+
+    /// Writes every valid entry to `writer`: one line per p2 entry, followed by the mappings of
+    /// its p1 table indented below it. Contiguous runs of pages are merged into one line.
+    pub fn dump<W: fmt::Write>(&self, writer: &mut W) -> fmt::Result {
+        type F = PageTableFlags;
+        for i in 0..ENTRY_COUNT {
+            let entry = &self.p2_table()[i];
+            if !entry.flags().contains(F::VALID) {
+                continue;
+            }
+            let note = if self.is_recursive_index(i) { Some("recursive") } else { None };
+            dump_p2_entry(writer, i, entry, self.is_huge(i), note)?;
+            if let Some(result) = self.p1_table(i, |p1| dump_p1(writer, i, p1)) {
+                result?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Counts the leaf entries and p1 tables of the page table, e.g. for memory accounting.
+    ///
+    /// The recursive entries are not counted.
+    pub fn stats(&self) -> PageTableStats {
+        self.stats_p2(0, ENTRY_COUNT - 1, None)
+    }
+
+    /// Like `stats`, but only counts the mappings of pages in `pages` and the p1 tables
+    /// covering them. A 4M page is counted if it overlaps `pages`.
+    pub fn stats_range(&self, pages: PageRange) -> PageTableStats {
+        match p2_index_range(pages) {
+            Some((first, last)) => self.stats_p2(first, last, Some(pages)),
+            None => PageTableStats::default(),
+        }
+    }
+
+    fn stats_p2(&self, first: usize, last: usize, pages: Option<PageRange>) -> PageTableStats {
+        type F = PageTableFlags;
+        let mut stats = PageTableStats::default();
+        for i in first..=last {
+            if self.is_recursive_index(i) || !self.p2_table()[i].flags().contains(F::VALID) {
+                continue;
+            }
+            if self.is_huge(i) {
+                stats.pages_4m += 1;
+            } else {
+                self.p1_table(i, |p1| stats.add_p1(i, p1, pages));
+            }
+        }
+        stats
+    }
+
+    /// Checks every valid entry for W^X violations, reserved encodings, misaligned 4M pages and
+    /// physical page numbers beyond `phys_bits` bits of physical address (at most 34).
+    ///
+    /// The findings are written to the start of `findings`. The total number of findings is
+    /// returned, which may be more than fit into `findings`. The recursive entries are skipped.
+    pub fn verify(&self, phys_bits: u32, findings: &mut [Finding]) -> usize {
+        type F = PageTableFlags;
+        let mut verifier = Verifier::new(phys_bits, findings);
+        for i in 0..ENTRY_COUNT {
+            if self.is_recursive_index(i) || !self.p2_table()[i].flags().contains(F::VALID) {
+                continue;
+            }
+            if verifier.check_p2_entry(i, &self.p2_table()[i]) {
+                self.p1_table(i, |p1| verifier.check_p1(i, p1));
+            }
+        }
+        verifier.count()
+    }
+
+    /// Removes `WRITABLE` from every writable page in `pages`.
+    ///
+    /// The entries are put into `SoftwareState::WriteProtected` so that `write_enable_all` can
+    /// restore exactly the pages that were writable before. A single flush for the whole range
+    /// is returned.
+    pub fn write_protect_all(&mut self, pages: PageRange) -> MapperFlushAll {
+        type F = PageTableFlags;
+        self.for_each_p1_entry(pages, |_, entry| {
+            let flags = entry.flags();
+            if flags.contains(F::VALID | F::WRITABLE) {
+                let frame = entry.frame();
+                entry.set(frame, flags.with_software_state(SoftwareState::WriteProtected));
+            }
+        });
+        MapperFlushAll::new()
+    }
+
+    /// Makes the pages in `pages` that were protected by `write_protect_all` writable again.
+    ///
+    /// Pages in any other `SoftwareState`, e.g. copy-on-write pages, are left alone.
+    pub fn write_enable_all(&mut self, pages: PageRange) -> MapperFlushAll {
+        type F = PageTableFlags;
+        self.for_each_p1_entry(pages, |_, entry| {
+            let flags = entry.flags();
+            if flags.contains(F::VALID) && flags.software_state() == SoftwareState::WriteProtected {
+                let frame = entry.frame();
+                let flags = flags.with_software_state(SoftwareState::None) | F::WRITABLE;
+                entry.set(frame, mark_soft_dirty(flags));
+            }
+        });
+        MapperFlushAll::new()
+    }
+
+    /// Calls `f` with every page in `pages` that has the `DIRTY` flag set and clears the flag.
+    ///
+    /// A single flush for the whole range is returned; it must be flushed before the pages are
+    /// written back, otherwise writes through stale TLB entries will not set `DIRTY` again.
+    pub fn drain_dirty<F>(&mut self, pages: PageRange, mut f: F) -> MapperFlushAll where F: FnMut(Page) {
+        type F = PageTableFlags;
+        self.for_each_p1_entry(pages, |page, entry| {
+            let flags = entry.flags();
+            if flags.contains(F::VALID | F::DIRTY) {
+                let frame = entry.frame();
+                entry.set(frame, flags - F::DIRTY);
+                f(page);
+            }
+        });
+        MapperFlushAll::new()
+    }
+
+    /// Calls `f` with the p1 entry of every page in `pages`, opening each p1 table only once.
+    ///
+    /// Pages whose p2 entry does not point to a p1 table are skipped.
+    fn for_each_p1_entry<F>(&mut self, pages: PageRange, mut f: F) where F: FnMut(Page, &mut PageTableEntry) {
+        let mut rest = pages;
+        while !rest.is_empty() {
+            let p2_index = rest.start.p2_index();
+            if !self.is_p1_table(p2_index) {
+                while !rest.is_empty() && rest.start.p2_index() == p2_index {
+                    rest.next();
+                }
+                continue;
+            }
+            self.edit_p1(p2_index, |p1| {
+                while !rest.is_empty() && rest.start.p2_index() == p2_index {
+                    let page = rest.next().unwrap();
+                    f(page, &mut p1[page.p1_index()]);
+                }
+            });
+        }
+    }
+
+    unsafe fn clean_up_p1_tables<D>(&mut self, first: usize, last: usize, deallocator: &mut D) where D: FrameDeallocator {
+        use asm::sfence_vma_all;
+        for p2_index in first..=last {
+            if !self.is_p1_table(p2_index) || !self.edit_p1(p2_index, |p1| p1.is_empty()) {
+                continue;
+            }
+            let frame = self.p2_table()[p2_index].frame();
+            self.p2_table_mut()[p2_index].set_unused();
+            sfence_vma_all();
+            deallocator.dealloc(frame);
+        }
+    }
+
+    fn is_p1_table(&self, p2_index: usize) -> bool {
+        type F = PageTableFlags;
+        !self.is_recursive_index(p2_index)
+            && self.p2_table()[p2_index].flags().contains(F::VALID)
+            && !self.is_huge(p2_index)
+    }
+
+    fn is_recursive_index(&self, p2_index: usize) -> bool {
+        p2_index == self.recursive_index || p2_index == self.rw_index
+    }
+
+    fn is_huge(&self, p2_index: usize) -> bool {
+        type F = PageTableFlags;
+        self.p2_table()[p2_index].flags().intersects(F::READABLE | F::WRITABLE | F::EXECUTABLE)
+    }
+
+    /// Maps the 4M page starting at `page` to the 4M of physical memory starting at `frame`,
+    /// using a single p2 entry.
+    ///
+    /// Both must be 4M aligned, and `flags` must contain `READABLE`, `WRITABLE` or
+    /// `EXECUTABLE`, otherwise the entry would point to a p1 table and `InvalidLeafFlags` is
+    /// returned. Returns `PageAlreadyMapped` if the p2 entry is in use, even by an empty p1
+    /// table. The returned flush removes the whole megapage from the TLB.
+    pub fn map_to_4mib(&mut self, page: Page, frame: Frame, flags: PageTableFlags) -> Result<MapperFlush, MapToError> {
+        check_megapage(page, frame, flags)?;
+        if self.is_recursive_index(page.p2_index()) {
+            return Err(MapToError::ReservedIndex);
+        }
+        if !self.p2_table()[page.p2_index()].is_unused() {
+            return Err(MapToError::PageAlreadyMapped);
         }
+        self.p2_table_mut()[page.p2_index()].set(frame, mark_soft_dirty(flags));
+        Ok(MapperFlush::new(page))
+    }
+
+    /// Removes the 4M mapping containing `page` and returns its first frame.
+    ///
+    /// Returns `PageNotMapped` if `page` is not mapped by a 4M page.
+    pub fn unmap_4mib(&mut self, page: Page) -> Result<(Frame, MapperFlush), UnmapError> {
+        type F = PageTableFlags;
+        if self.is_recursive_index(page.p2_index()) {
+            return Err(UnmapError::ReservedIndex);
+        }
+        let entry = &mut self.p2_table_mut()[page.p2_index()];
+        if !entry.flags().contains(F::VALID) || !entry.flags().intersects(F::READABLE | F::WRITABLE | F::EXECUTABLE) {
+            return Err(UnmapError::PageNotMapped);
+        }
+        let frame = entry.frame();
+        entry.set_unused();
+        Ok((frame, MapperFlush::new(page)))
+    }
+
+    /// Maps `page` like `Mapper::map_to`, but marks the mapping and any newly created p1 table
+    /// as `GLOBAL`, so it is shared by all address spaces and survives ASID-scoped flushes.
+    ///
+    /// Use `MapperFlush::flush_global` to flush the mapping.
+    pub fn map_global<A>(&mut self, page: Page, frame: Frame, flags: PageTableFlags, allocator: &mut A) -> Result<MapperFlush, MapToError>
+        where A: FrameAllocator,
+    {
+        type F = PageTableFlags;
+        self.map_to_with_table_flags(page, frame, flags | F::GLOBAL, F::VALID | F::GLOBAL, allocator)
     }
 
-    fn create_p1_if_not_exist<A>(&mut self, p2_index: usize, allocator: &mut A) -> Result<(), MapToError>
+    /// Creates the p1 table for `p2_index` with `table_flags` if it does not exist yet.
+    pub(crate) fn create_p1_if_not_exist<A>(&mut self, p2_index: usize, table_flags: PageTableFlags, allocator: &mut A) -> Result<(), MapToError>
         where A: FrameAllocator,
     {
         type F = PageTableFlags;
-        if self.p2[p2_index].is_unused() {
+        if self.is_recursive_index(p2_index) {
+            return Err(MapToError::ReservedIndex);
+        }
+        if self.p2_table()[p2_index].is_unused() {
             if let Some(frame) = allocator.alloc() {
-                self.p2[p2_index].set(frame, F::VALID);
+                self.p2_table_mut()[p2_index].set(frame, table_flags | F::VALID);
                 self.edit_p1(p2_index, |p1| p1.zero());
             } else {
                 return Err(MapToError::FrameAllocationFailed);
             }
+        } else if self.is_huge(p2_index) {
+            return Err(MapToError::ParentEntryHugePage);
         }
         Ok(())
     }
 
     /// Edit a p1 page.
     /// During the editing, the flag of entry `p2[p2_index]` is temporarily set to V+R+W.
+    ///
+    /// Callers must check that `p2[p2_index]` points to a p1 table; the assertions below only
+    /// guard against bugs in this module.
     fn edit_p1<F, T>(&mut self, p2_index: usize, f: F) -> T where F: FnOnce(&mut PageTable) -> T {
+        let p1 = unsafe { self.open_p1(p2_index) };
+        let ret = f(unsafe { &mut *p1 });
+        unsafe { self.close_p1(p2_index) };
+        ret
+    }
+
+    /// Read a p1 page, the read-only counterpart of `edit_p1`.
+    ///
+    /// The flag of entry `p2[p2_index]` is still toggled while `f` runs. This only needs `&self`
+    /// because the p2 table is held as a raw pointer and `RecursivePageTable` is not `Sync`.
+    fn read_p1<F, T>(&self, p2_index: usize, f: F) -> T where F: FnOnce(&PageTable) -> T {
+        let p1 = unsafe { self.open_p1(p2_index) };
+        let ret = f(unsafe { &*p1 });
+        unsafe { self.close_p1(p2_index) };
+        ret
+    }
+
+    /// Sets V+R+W on `p2[p2_index]` and returns the p1 table's address in the recursive mapping.
+    ///
+    /// Must be paired with `close_p1`, and no reference into the p2 table may be alive meanwhile.
+    unsafe fn open_p1(&self, p2_index: usize) -> *mut PageTable {
         type F = PageTableFlags;
-        let flags = self.p2[p2_index].flags_mut();
+        let flags = (&mut *self.p2)[p2_index].flags_mut();
         assert_ne!(p2_index, self.recursive_index, "can not edit recursive index");
-        assert_ne!(p2_index, self.recursive_index + 1, "can not edit recursive index");
+        assert_ne!(p2_index, self.rw_index, "can not edit recursive index");
         assert!(flags.contains(F::VALID), "try to edit a nonexistent p1 table");
         assert!(!flags.contains(F::READABLE) && !flags.contains(F::WRITABLE), "try to edit a 4M page as p1 table");
         flags.insert(F::READABLE | F::WRITABLE);
         let p1 = Page::from_page_table_indices(self.recursive_index, p2_index);
-        let p1 = unsafe{ &mut *(p1.start_address().as_usize() as *mut PageTable) };
-        let ret = f(p1);
-        flags.remove(F::READABLE | F::WRITABLE);
-        ret
+        p1.start_address().as_usize() as *mut PageTable
+    }
+
+    /// Clears the R+W flags set by `open_p1`.
+    unsafe fn close_p1(&self, p2_index: usize) {
+        (&mut *self.p2)[p2_index].flags_mut().remove(PageTableFlags::READABLE | PageTableFlags::WRITABLE);
     }
 }
 
 impl<'a> Mapper for RecursivePageTable<'a> {
-    fn map_to<A>(&mut self, page: Page, frame: Frame, flags: PageTableFlags, allocator: &mut A) -> Result<MapperFlush, MapToError>
+    fn map_to_with_table_flags<A>(&mut self, page: Page, frame: Frame, flags: PageTableFlags, table_flags: PageTableFlags, allocator: &mut A) -> Result<MapperFlush, MapToError>
         where A: FrameAllocator,
     {
-        use self::PageTableFlags as Flags;
-        self.create_p1_if_not_exist(page.p2_index(), allocator)?;
+        check_table_flags(table_flags)?;
+        self.create_p1_if_not_exist(page.p2_index(), table_flags, allocator)?;
         self.edit_p1(page.p2_index(), |p1| {
             if !p1[page.p1_index()].is_unused() {
                 return Err(MapToError::PageAlreadyMapped);
             }
-            p1[page.p1_index()].set(frame, flags);
+            p1[page.p1_index()].set(frame, mark_soft_dirty(flags));
             Ok(MapperFlush::new(page))
         })
     }
 
+    fn map_range<A>(&mut self, pages: PageRange, frames: FrameRange, flags: PageTableFlags, allocator: &mut A) -> Result<MapperFlushAll, MapToError>
+        where A: FrameAllocator,
+    {
+        if pages.len() != frames.len() {
+            return Err(MapToError::RangeLengthMismatch);
+        }
+        // Fill each p1 table in one editing window instead of one window per page.
+        let mut rest = pages;
+        let mut frames = frames;
+        while !rest.is_empty() {
+            let p2_index = rest.start.p2_index();
+            self.create_p1_if_not_exist(p2_index, PageTableFlags::VALID, allocator)?;
+            self.edit_p1(p2_index, |p1| {
+                while !rest.is_empty() && rest.start.p2_index() == p2_index {
+                    let page = rest.next().unwrap();
+                    if !p1[page.p1_index()].is_unused() {
+                        return Err(MapToError::PageAlreadyMapped);
+                    }
+                    p1[page.p1_index()].set(frames.next().unwrap(), mark_soft_dirty(flags));
+                }
+                Ok(())
+            })?;
+        }
+        Ok(MapperFlushAll::new())
+    }
+
     fn unmap(&mut self, page: Page) -> Result<(Frame, MapperFlush), UnmapError> {
         use self::PageTableFlags as Flags;
-        if self.p2[page.p2_index()].is_unused() {
+        if self.is_recursive_index(page.p2_index()) {
+            return Err(UnmapError::ReservedIndex);
+        }
+        if self.p2_table()[page.p2_index()].is_unused() {
             return Err(UnmapError::PageNotMapped);
         }
+        if self.is_huge(page.p2_index()) {
+            return Err(UnmapError::ParentEntryHugePage);
+        }
         self.edit_p1(page.p2_index(), |p1| {
             let p1_entry = &mut p1[page.p1_index()];
             if !p1_entry.flags().contains(Flags::VALID) {
@@ -191,17 +1093,122 @@ impl<'a> Mapper for RecursivePageTable<'a> {
         })
     }
 
+    fn update_flags(&mut self, page: Page, flags: PageTableFlags) -> Result<MapperFlush, FlagUpdateError> {
+        use self::PageTableFlags as Flags;
+        check_leaf_flags(flags)?;
+        if self.is_recursive_index(page.p2_index()) {
+            return Err(FlagUpdateError::ReservedIndex);
+        }
+        if self.p2_table()[page.p2_index()].is_unused() {
+            return Err(FlagUpdateError::PageNotMapped);
+        }
+        if self.is_huge(page.p2_index()) {
+            return Err(FlagUpdateError::ParentEntryHugePage);
+        }
+        self.edit_p1(page.p2_index(), |p1| {
+            let p1_entry = &mut p1[page.p1_index()];
+            if !p1_entry.flags().contains(Flags::VALID) {
+                return Err(FlagUpdateError::PageNotMapped);
+            }
+            let frame = p1_entry.frame();
+            p1_entry.set(frame, mark_soft_dirty(flags));
+            Ok(MapperFlush::new(page))
+        })
+    }
+
+    fn unmap_range<F>(&mut self, pages: PageRange, mut f: F) -> Result<MapperFlushAll, UnmapError>
+        where F: FnMut(Page, Frame),
+    {
+        use self::PageTableFlags as Flags;
+        let mut rest = pages;
+        while !rest.is_empty() {
+            let p2_index = rest.start.p2_index();
+            if self.is_recursive_index(p2_index) {
+                return Err(UnmapError::ReservedIndex);
+            }
+            if self.p2_table()[p2_index].is_unused() {
+                while !rest.is_empty() && rest.start.p2_index() == p2_index {
+                    rest.next();
+                }
+                continue;
+            }
+            if self.is_huge(p2_index) {
+                return Err(UnmapError::ParentEntryHugePage);
+            }
+            self.edit_p1(p2_index, |p1| {
+                while !rest.is_empty() && rest.start.p2_index() == p2_index {
+                    let page = rest.next().unwrap();
+                    let p1_entry = &mut p1[page.p1_index()];
+                    if p1_entry.flags().contains(Flags::VALID) {
+                        let frame = p1_entry.frame();
+                        p1_entry.set_unused();
+                        f(page, frame);
+                    }
+                }
+            });
+        }
+        Ok(MapperFlushAll::new())
+    }
+
     fn translate_page(&self, page: Page) -> Option<Frame> {
-        if self.p2[page.p2_index()].is_unused() {
+        let p2_entry = &self.p2_table()[page.p2_index()];
+        if p2_entry.is_unused() || self.is_recursive_index(page.p2_index()) {
             return None;
         }
-        let self_mut = unsafe{ &mut *(self as *const _ as *mut Self) };
-        self_mut.edit_p1(page.p2_index(), |p1| {
+        if self.is_huge(page.p2_index()) {
+            // 4M page: the frame is at the same offset inside the megapage as the page.
+            let addr = p2_entry.addr().as_u32() + ((page.p1_index() as u32) << 12);
+            return Some(Frame::of_addr(PhysAddr::new(addr)));
+        }
+        self.read_p1(page.p2_index(), |p1| {
             let p1_entry = &p1[page.p1_index()];
-            if p1_entry.is_unused() {
+            if !p1_entry.flags().contains(PageTableFlags::VALID) {
                 return None;
             }
             Some(p1_entry.frame())
         })
     }
 }
+
+impl<'a> CleanUp for RecursivePageTable<'a> {
+    unsafe fn clean_up<D>(&mut self, deallocator: &mut D) where D: FrameDeallocator {
+        self.clean_up_p1_tables(0, ENTRY_COUNT - 1, deallocator);
+    }
+
+    unsafe fn clean_up_range<D>(&mut self, pages: PageRange, deallocator: &mut D) where D: FrameDeallocator {
+        if let Some((first, last)) = p2_index_range(pages) {
+            self.clean_up_p1_tables(first, last, deallocator);
+        }
+    }
+}
+
+impl<'a> Translate for RecursivePageTable<'a> {
+    /// Addresses inside the recursive mapping window are reported as `NotMapped`.
+    fn translate(&self, addr: VirtAddr) -> TranslateResult {
+        type F = PageTableFlags;
+        let p2_index = addr.p2_index();
+        let p2_entry = &self.p2_table()[p2_index];
+        if !p2_entry.flags().contains(F::VALID) || self.is_recursive_index(p2_index) {
+            return TranslateResult::NotMapped;
+        }
+        if self.is_huge(p2_index) {
+            return translate_leaf(p2_entry, PageSize::Size4MiB, addr.as_usize() & 0x3fffff);
+        }
+        self.read_p1(p2_index, |p1| {
+            let p1_entry = &p1[addr.p1_index()];
+            if !p1_entry.flags().contains(F::VALID) {
+                return TranslateResult::NotMapped;
+            }
+            translate_leaf(p1_entry, PageSize::Size4KiB, addr.page_offset())
+        })
+    }
+
+    fn region_mapped(&self, addr: VirtAddr) -> bool {
+        let p2_index = addr.p2_index();
+        self.p2_table()[p2_index].flags().contains(PageTableFlags::VALID) && !self.is_recursive_index(p2_index)
+    }
+
+    fn leaf_entry(&self, page: Page) -> Option<PageTableEntry> {
+        self.get_entry(page, |entry| *entry)
+    }
+}
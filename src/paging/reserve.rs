@@ -0,0 +1,130 @@
+use super::frame_alloc::*;
+use super::mapped::*;
+use super::offset::*;
+use super::page_table::*;
+use super::recursive::*;
+use addr::*;
+
+/// A `Mapper` whose 4K leaf entries can be accessed directly.
+///
+/// This is what `reserve` and `commit` need to write entries that aren't mappings.
+pub trait LeafEntries: Mapper {
+    /// Runs `f` on the leaf entry of `page`, or returns `None` if there is no p1 table for it.
+    ///
+    /// Changes made by `f` must be flushed from the TLB by the caller.
+    fn leaf_entry_mut<F, T>(&mut self, page: Page, f: F) -> Option<T> where F: FnOnce(&mut PageTableEntry) -> T;
+
+    /// Creates the p1 table for `page` if it does not exist yet, allocating it from `allocator`.
+    fn ensure_p1_table<A>(&mut self, page: Page, allocator: &mut A) -> Result<(), MapToError> where A: FrameAllocator;
+}
+
+impl<'a> LeafEntries for RecursivePageTable<'a> {
+    fn leaf_entry_mut<F, T>(&mut self, page: Page, f: F) -> Option<T> where F: FnOnce(&mut PageTableEntry) -> T {
+        self.get_entry_mut(page, f)
+    }
+
+    fn ensure_p1_table<A>(&mut self, page: Page, allocator: &mut A) -> Result<(), MapToError> where A: FrameAllocator {
+        self.create_p1_if_not_exist(page.p2_index(), PageTableFlags::VALID, allocator)
+    }
+}
+
+impl<'a, P: PhysToVirt> LeafEntries for MappedPageTable<'a, P> {
+    fn leaf_entry_mut<F, T>(&mut self, page: Page, f: F) -> Option<T> where F: FnOnce(&mut PageTableEntry) -> T {
+        self.get_entry_mut(page, f)
+    }
+
+    fn ensure_p1_table<A>(&mut self, page: Page, allocator: &mut A) -> Result<(), MapToError> where A: FrameAllocator {
+        self.create_p1_if_not_exist(page.p2_index(), PageTableFlags::VALID, allocator)
+    }
+}
+
+impl<'a> LeafEntries for OffsetPageTable<'a> {
+    fn leaf_entry_mut<F, T>(&mut self, page: Page, f: F) -> Option<T> where F: FnOnce(&mut PageTableEntry) -> T {
+        self.get_entry_mut(page, f)
+    }
+
+    fn ensure_p1_table<A>(&mut self, page: Page, allocator: &mut A) -> Result<(), MapToError> where A: FrameAllocator {
+        self.create_p1_if_not_exist(page.p2_index(), PageTableFlags::VALID, allocator)
+    }
+}
+
+/// An error indicating that a `commit` call failed.
+#[derive(Debug)]
+pub enum CommitError {
+    /// The page is not reserved: it is mapped, unused or part of a 4M page.
+    NotReserved,
+    /// The flags are not those of a valid leaf entry, see `commit`.
+    InvalidFlags,
+}
+
+/// Reserves every page in `pages`, tagging it with `tag`.
+///
+/// The p1 tables are created up front, so a later `commit` never allocates. The leaf entries
+/// are left non-present, see `PageTableEntry::set_reserved`, so the first access to a reserved
+/// page faults and the fault handler can look up the tag with `reservation` and `commit` a
+/// frame. This is how demand-paged heaps and stacks are set up.
+///
+/// Returns `PageAlreadyMapped` if a page is in use, in which case the pages before it stay
+/// reserved.
+pub fn reserve<M, A>(mapper: &mut M, pages: PageRange, tag: u32, allocator: &mut A) -> Result<(), MapToError>
+    where M: LeafEntries, A: FrameAllocator,
+{
+    for page in pages {
+        set_non_present(mapper, page, allocator, |entry| entry.set_reserved(tag))?;
+    }
+    Ok(())
+}
+
+/// Creates the p1 table for `page` if needed and runs `f` on its leaf entry, which must leave
+/// the entry non-present.
+///
+/// Returns `PageAlreadyMapped` if the entry of `page` is in use.
+pub(crate) fn set_non_present<M, A, F>(mapper: &mut M, page: Page, allocator: &mut A, f: F) -> Result<(), MapToError>
+    where M: LeafEntries, A: FrameAllocator, F: FnOnce(&mut PageTableEntry),
+{
+    mapper.ensure_p1_table(page, allocator)?;
+    mapper.leaf_entry_mut(page, |entry| {
+        if !entry.is_unused() {
+            return Err(MapToError::PageAlreadyMapped);
+        }
+        f(entry);
+        Ok(())
+    }).expect("p1 table vanished")
+}
+
+/// Returns the tag `page` was reserved with, or `None` if it is not reserved.
+pub fn reservation<M: LeafEntries>(mapper: &mut M, page: Page) -> Option<u32> {
+    mapper.leaf_entry_mut(page, |entry| entry.reserved_tag())?
+}
+
+/// Maps the reserved `page` to `frame` and returns the tag it was reserved with.
+///
+/// `flags` are written to the leaf entry as they are, so they must contain `VALID` and at
+/// least one of `READABLE`, `WRITABLE` and `EXECUTABLE`, and must not have `WRITABLE` without
+/// `READABLE`; otherwise `InvalidFlags` is returned.
+pub fn commit<M: LeafEntries>(mapper: &mut M, page: Page, frame: Frame, flags: PageTableFlags) -> Result<(u32, MapperFlush), CommitError> {
+    type F = PageTableFlags;
+    if !flags.contains(F::VALID) || !flags.intersects(F::READABLE | F::WRITABLE | F::EXECUTABLE)
+        || flags & (F::READABLE | F::WRITABLE) == F::WRITABLE
+    {
+        return Err(CommitError::InvalidFlags);
+    }
+    let tag = mapper.leaf_entry_mut(page, |entry| {
+        let tag = entry.reserved_tag()?;
+        entry.set(frame, mark_soft_dirty(flags));
+        Some(tag)
+    });
+    match tag {
+        Some(Some(tag)) => Ok((tag, MapperFlush::new(page))),
+        _ => Err(CommitError::NotReserved),
+    }
+}
+
+/// Drops the reservation of every reserved page in `pages`. Committed pages are left alone.
+///
+/// Empty p1 tables are not freed; use `CleanUp::clean_up_range` for that.
+pub fn release<M: LeafEntries>(mapper: &mut M, pages: PageRange) {
+    for page in pages {
+        mapper.leaf_entry_mut(page, |entry| if entry.is_reserved() { entry.set_unused() });
+    }
+}
@@ -0,0 +1,75 @@
+use super::frame_alloc::*;
+use super::page_table::*;
+use super::phys::*;
+use super::recursive::*;
+use addr::*;
+
+/// Describes how memory is mapped to its shadow, as used by address sanitizers.
+///
+/// The shadow byte of `addr` lives at `(addr >> scale) + offset`.
+#[derive(Debug, Copy, Clone)]
+pub struct ShadowConfig {
+    /// Each shadow byte covers `1 << scale` bytes of memory.
+    pub scale: u32,
+    pub offset: usize,
+}
+
+impl ShadowConfig {
+    pub fn new(scale: u32, offset: usize) -> Self {
+        ShadowConfig { scale, offset }
+    }
+
+    /// Returns the address of the shadow byte of `addr`.
+    pub fn shadow_addr(&self, addr: VirtAddr) -> VirtAddr {
+        VirtAddr::new((addr.as_usize() >> self.scale) + self.offset)
+    }
+
+    /// Returns the shadow pages covering the memory range `[start, start + len)`.
+    pub fn shadow_pages(&self, start: VirtAddr, len: usize) -> PageRange {
+        let end = self.shadow_addr(VirtAddr::new(start.as_usize() + len));
+        Page::range(self.shadow_addr(start), end)
+    }
+}
+
+/// Maps every page in `pages` read-only to the shared `zero_frame`.
+///
+/// This is how a shadow region is set up cheaply: all shadow bytes read as zero until the
+/// shadow page is populated with `populate_shadow_page` on the first write.
+pub fn map_shadow_zero<M, A>(mapper: &mut M, pages: PageRange, zero_frame: Frame, allocator: &mut A) -> Result<MapperFlushAll, MapToError>
+    where M: Mapper, A: FrameAllocator,
+{
+    type F = PageTableFlags;
+    for page in pages {
+        mapper.map_to(page, zero_frame, F::VALID | F::READABLE, allocator)?.ignore();
+    }
+    Ok(MapperFlushAll::new())
+}
+
+/// An error returned by `populate_shadow_page`.
+#[derive(Debug)]
+pub enum ShadowError {
+    /// The page doesn't map the zero frame by a 4K page: it is unmapped, part of a 4M page or
+    /// already populated.
+    NotZeroMapped,
+    /// No frame could be allocated for the shadow page.
+    FrameAllocationFailed,
+}
+
+/// Replaces the mapping of a shadow page to `zero_frame`, as set up by `map_shadow_zero`, by a
+/// private frame, which is zeroed through `phys`.
+///
+/// The page keeps its flags and becomes writable.
+pub fn populate_shadow_page<M, P, A>(mapper: &mut M, page: Page, zero_frame: Frame, phys: &mut P, allocator: &mut A) -> Result<(), ShadowError>
+    where M: Mapper + Translate, P: PhysWrite, A: FrameAllocator,
+{
+    let flags = match mapper.translate(page.start_address()) {
+        TranslateResult::Mapped { frame, flags, size: PageSize::Size4KiB, .. } if frame == zero_frame => flags,
+        _ => return Err(ShadowError::NotZeroMapped),
+    };
+    let frame = allocator.alloc().ok_or(ShadowError::FrameAllocationFailed)?;
+    phys.clear_frame(frame);
+    mapper.unmap(page).expect("page vanished").1.ignore();
+    mapper.map_to(page, frame, flags | PageTableFlags::WRITABLE, allocator)
+        .expect("p1 table vanished").flush();
+    Ok(())
+}
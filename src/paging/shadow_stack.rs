@@ -0,0 +1,28 @@
+use super::frame_alloc::*;
+use super::page_table::*;
+use super::phys::*;
+use super::recursive::*;
+use addr::*;
+
+impl PageTableFlags {
+    /// Returns the flags of a Zicfiss shadow stack page.
+    ///
+    /// Shadow stack pages use the encoding `W` without `R`, which was reserved before Zicfiss.
+    /// They can only be written by shadow stack instructions. `ACCESSED` and `DIRTY` are preset,
+    /// so no fault is taken on implementations that don't update them in hardware.
+    pub fn shadow_stack(user: bool) -> Self {
+        type F = PageTableFlags;
+        let flags = F::VALID | F::WRITABLE | F::ACCESSED | F::DIRTY;
+        if user { flags | F::USER } else { flags }
+    }
+}
+
+/// Allocates zeroed frames for every page in `pages` and maps them as shadow stack.
+///
+/// Regular stores to shadow stack pages fault, so the frames are zeroed through `phys` before
+/// they are mapped, see `Mapper::map_range_alloc`.
+pub fn map_shadow_stack<M, P, A>(mapper: &mut M, pages: PageRange, user: bool, phys: &mut P, allocator: &mut A) -> Result<MapperFlushAll, MapToError>
+    where M: Mapper, P: PhysWrite, A: FrameAllocator + FrameDeallocator,
+{
+    mapper.map_range_alloc(pages, PageTableFlags::shadow_stack(user), phys, allocator)
+}
@@ -0,0 +1,86 @@
+use super::page_table::*;
+use super::recursive::*;
+use super::walk::*;
+use addr::*;
+
+/// An error returned by `resolve_soft_dirty_fault`.
+#[derive(Debug)]
+pub enum SoftDirtyError {
+    /// The page is not mapped by a 4K page.
+    PageNotMapped,
+    /// The page was not write-protected by `clear_soft_dirty`, so the write fault is genuine.
+    NotSoftDirtyProtected,
+}
+
+/// Clears the soft-dirty bit of every 4K page in `pages` by write-protecting the writable ones.
+///
+/// With the `soft-dirty` feature, every mapping that is made writable by `map_to`,
+/// `update_flags` or `RecursivePageTable::write_enable_all` is marked soft-dirty with the
+/// software bit `RESERVED2`. The writable pages are put into
+/// `SoftwareState::SoftDirtyProtected`, which neither `write_enable_all` nor the copy-on-write
+/// code interpret, so the next write faults and `resolve_soft_dirty_fault` can make the page
+/// writable and soft-dirty again.
+pub fn clear_soft_dirty<M>(mapper: &mut M, pages: PageRange) -> MapperFlushAll where M: Mapper + Translate {
+    type F = PageTableFlags;
+    for page in pages {
+        if let TranslateResult::Mapped { flags, size: PageSize::Size4KiB, .. } = mapper.translate(page.start_address()) {
+            if !flags.contains(F::WRITABLE) {
+                continue;
+            }
+            let flags = flags.with_software_state(SoftwareState::SoftDirtyProtected);
+            mapper.update_flags(page, flags).expect("page vanished").ignore();
+        }
+    }
+    MapperFlushAll::new()
+}
+
+/// Resolves a write fault on a page write-protected by `clear_soft_dirty`.
+///
+/// Makes the page writable again, which marks it soft-dirty.
+pub fn resolve_soft_dirty_fault<M>(mapper: &mut M, page: Page) -> Result<MapperFlush, SoftDirtyError>
+    where M: Mapper + Translate,
+{
+    let flags = match mapper.translate(page.start_address()) {
+        TranslateResult::Mapped { flags, size: PageSize::Size4KiB, .. } => flags,
+        _ => return Err(SoftDirtyError::PageNotMapped),
+    };
+    if flags.software_state() != SoftwareState::SoftDirtyProtected {
+        return Err(SoftDirtyError::NotSoftDirtyProtected);
+    }
+    let flags = flags.with_software_state(SoftwareState::None) | PageTableFlags::WRITABLE;
+    Ok(mapper.update_flags(page, flags).expect("page vanished"))
+}
+
+/// An iterator over the soft-dirty 4K pages of a page table, returned by `iter_soft_dirty`.
+pub struct SoftDirtyPages<'a, T: Translate + 'a> {
+    mappings: Mappings<'a, T>,
+}
+
+impl<'a, T: Translate> Iterator for SoftDirtyPages<'a, T> {
+    type Item = Page;
+
+    fn next(&mut self) -> Option<Page> {
+        type F = PageTableFlags;
+        while let Some((page, _, flags, size)) = self.mappings.next() {
+            if size != PageSize::Size4KiB {
+                continue;
+            }
+            let dirty = match flags.software_state() {
+                SoftwareState::WriteProtected | SoftwareState::CopyOnWrite => true,
+                _ => flags.contains(F::WRITABLE | F::RESERVED2),
+            };
+            if dirty {
+                return Some(page);
+            }
+        }
+        None
+    }
+}
+
+/// Returns the pages in `pages` that were marked soft-dirty since the last `clear_soft_dirty`.
+///
+/// Write-protected and copy-on-write pages don't keep their soft-dirty bit, so they are
+/// always reported; the result may contain pages that weren't written, but never misses one.
+pub fn iter_soft_dirty<T: Translate>(table: &T, pages: PageRange) -> SoftDirtyPages<T> {
+    SoftDirtyPages { mappings: Mappings::range(table, pages) }
+}
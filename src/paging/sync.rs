@@ -0,0 +1,73 @@
+use core::cell::UnsafeCell;
+use core::sync::atomic::{spin_loop_hint, AtomicBool, Ordering};
+use super::frame_alloc::*;
+use super::page_table::*;
+use super::recursive::*;
+use addr::*;
+
+/// A `RecursivePageTable` that can be shared between harts.
+///
+/// All operations take `&self` and are serialized by a spin lock, so the table can live in a
+/// `static` and be used from concurrent fault handlers.
+pub struct SyncRecursivePageTable<'a> {
+    locked: AtomicBool,
+    table: UnsafeCell<RecursivePageTable<'a>>,
+}
+
+unsafe impl<'a> Sync for SyncRecursivePageTable<'a> {}
+
+/// Releases the spin lock when dropped, so a panic in the locked closure doesn't leave it held.
+struct LockGuard<'l>(&'l AtomicBool);
+
+impl<'l> Drop for LockGuard<'l> {
+    fn drop(&mut self) {
+        self.0.store(false, Ordering::Release);
+    }
+}
+
+impl<'a> SyncRecursivePageTable<'a> {
+    pub fn new(table: RecursivePageTable<'a>) -> Self {
+        SyncRecursivePageTable {
+            locked: AtomicBool::new(false),
+            table: UnsafeCell::new(table),
+        }
+    }
+
+    /// Returns the wrapped page table.
+    pub fn into_inner(self) -> RecursivePageTable<'a> {
+        self.table.into_inner()
+    }
+
+    /// Runs `f` with exclusive access to the page table.
+    ///
+    /// Must not be called recursively from within `f`, which would deadlock.
+    pub fn lock<F, T>(&self, f: F) -> T where F: FnOnce(&mut RecursivePageTable<'a>) -> T {
+        while self.locked.compare_exchange(false, true, Ordering::Acquire, Ordering::Relaxed).is_err() {
+            spin_loop_hint();
+        }
+        let _guard = LockGuard(&self.locked);
+        f(unsafe { &mut *self.table.get() })
+    }
+
+    /// Creates a new mapping in the page table. See `Mapper::map_to`.
+    pub fn map_to<A>(&self, page: Page, frame: Frame, flags: PageTableFlags, allocator: &mut A) -> Result<MapperFlush, MapToError>
+        where A: FrameAllocator,
+    {
+        self.lock(|table| table.map_to(page, frame, flags, allocator))
+    }
+
+    /// Removes a mapping from the page table. See `Mapper::unmap`.
+    pub fn unmap(&self, page: Page) -> Result<(Frame, MapperFlush), UnmapError> {
+        self.lock(|table| table.unmap(page))
+    }
+
+    /// Updates the flags of an existing mapping. See `Mapper::update_flags`.
+    pub fn update_flags(&self, page: Page, flags: PageTableFlags) -> Result<MapperFlush, FlagUpdateError> {
+        self.lock(|table| table.update_flags(page, flags))
+    }
+
+    /// Return the frame that the specified page is mapped to. See `Mapper::translate_page`.
+    pub fn translate_page(&self, page: Page) -> Option<Frame> {
+        self.lock(|table| table.translate_page(page))
+    }
+}
@@ -0,0 +1,98 @@
+use super::page_table::*;
+use addr::*;
+
+/// A problem found by `RecursivePageTable::verify`.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Finding {
+    /// The page at the address is mapped both writable and executable, violating W^X.
+    WritableExecutable(VirtAddr),
+    /// The entry mapping the address uses a reserved encoding: `WRITABLE` without `READABLE`,
+    /// a non-leaf p1 entry, or `USER`, `ACCESSED` or `DIRTY` on a non-leaf entry.
+    ///
+    /// The latter is the RISC-V counterpart of a user page under a supervisor-only parent:
+    /// user access is controlled by the leaf alone, and the bits are reserved on tables.
+    ReservedEncoding(VirtAddr),
+    /// The 4M page at the address is not aligned to 4M physically.
+    MisalignedMegapage(VirtAddr),
+    /// The entry mapping the address points beyond the physical address width.
+    PpnOutOfRange(VirtAddr),
+}
+
+/// Collects the findings of a page table walk into a caller provided slice.
+pub(crate) struct Verifier<'b> {
+    findings: &'b mut [Finding],
+    count: usize,
+    max_ppn: u64,
+}
+
+impl<'b> Verifier<'b> {
+    pub(crate) fn new(phys_bits: u32, findings: &'b mut [Finding]) -> Self {
+        assert!(phys_bits >= 12 && phys_bits <= 34, "Sv32 physical addresses have at most 34 bits");
+        Verifier { findings, count: 0, max_ppn: 1u64 << (phys_bits - 12) }
+    }
+
+    /// Returns the total number of findings, including the ones that didn't fit.
+    pub(crate) fn count(&self) -> usize {
+        self.count
+    }
+
+    fn report(&mut self, finding: Finding) {
+        if self.count < self.findings.len() {
+            self.findings[self.count] = finding;
+        }
+        self.count += 1;
+    }
+
+    /// Checks the valid p2 entry `p2_index` and returns whether it points to a p1 table.
+    pub(crate) fn check_p2_entry(&mut self, p2_index: usize, entry: &PageTableEntry) -> bool {
+        type F = PageTableFlags;
+        let addr = VirtAddr::new(p2_index << 22);
+        let flags = entry.flags();
+        if (entry.ppn() as u64) >= self.max_ppn {
+            self.report(Finding::PpnOutOfRange(addr));
+            return false;
+        }
+        if flags.intersects(F::READABLE | F::WRITABLE | F::EXECUTABLE) {
+            self.check_leaf(addr, flags);
+            if entry.ppn() & 0x3ff != 0 {
+                self.report(Finding::MisalignedMegapage(addr));
+            }
+            return false;
+        }
+        if flags.intersects(F::USER | F::ACCESSED | F::DIRTY) {
+            self.report(Finding::ReservedEncoding(addr));
+        }
+        true
+    }
+
+    /// Checks the valid entries of the p1 table at `p2_index`.
+    pub(crate) fn check_p1(&mut self, p2_index: usize, p1: &PageTable) {
+        type F = PageTableFlags;
+        for i in 0..ENTRY_COUNT {
+            let entry = &p1[i];
+            let flags = entry.flags();
+            let addr = VirtAddr::new(p2_index << 22 | i << 12);
+            if !flags.contains(F::VALID) {
+                continue;
+            }
+            if (entry.ppn() as u64) >= self.max_ppn {
+                self.report(Finding::PpnOutOfRange(addr));
+            }
+            if !flags.intersects(F::READABLE | F::WRITABLE | F::EXECUTABLE) {
+                self.report(Finding::ReservedEncoding(addr));
+                continue;
+            }
+            self.check_leaf(addr, flags);
+        }
+    }
+
+    fn check_leaf(&mut self, addr: VirtAddr, flags: PageTableFlags) {
+        type F = PageTableFlags;
+        if flags.contains(F::WRITABLE) && !flags.contains(F::READABLE) && !cfg!(feature = "cfi") {
+            self.report(Finding::ReservedEncoding(addr));
+        }
+        if flags.contains(F::WRITABLE | F::EXECUTABLE) {
+            self.report(Finding::WritableExecutable(addr));
+        }
+    }
+}
@@ -0,0 +1,143 @@
+use super::page_table::*;
+use super::recursive::*;
+use addr::*;
+
+/// An iterator over the valid leaf mappings of a page table.
+///
+/// Yields `(page, frame, flags, size)` for every mapping, in ascending order of virtual address.
+/// A 4M mapping is yielded once, with its first page and frame. Entries that `translate`
+/// reports as `InvalidEntry` are skipped, as is the recursive window of a `RecursivePageTable`.
+/// 4M regions that `Translate::region_mapped` reports as unmapped are skipped as a whole.
+pub struct Mappings<'a, T: Translate + 'a> {
+    table: &'a T,
+    /// Page number of the next page to look at.
+    next: usize,
+    /// Page number of the end of the walk, exclusive.
+    end: usize,
+}
+
+const PAGE_COUNT: usize = 1 << 20;
+const MEGAPAGE_PAGES: usize = 1 << 10;
+
+impl<'a, T: Translate> Mappings<'a, T> {
+    /// Walks the whole address space of `table`.
+    pub fn new(table: &'a T) -> Self {
+        Mappings { table, next: 0, end: PAGE_COUNT }
+    }
+
+    /// Walks the mappings of `table` overlapping `pages`.
+    ///
+    /// A 4M mapping that starts before `pages` is yielded with its own first page, which lies
+    /// outside the range.
+    pub fn range(table: &'a T, pages: PageRange) -> Self {
+        Mappings { table, next: pages.start.number(), end: pages.end.number().max(pages.start.number()) }
+    }
+}
+
+impl<'a, T: Translate> Iterator for Mappings<'a, T> {
+    type Item = (Page, Frame, PageTableFlags, PageSize);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while self.next < self.end {
+            let addr = VirtAddr::new(self.next * PAGE_SIZE);
+            match self.table.translate(addr) {
+                TranslateResult::Mapped { frame, flags, size: PageSize::Size4MiB, .. } => {
+                    let first = self.next & !(MEGAPAGE_PAGES - 1);
+                    self.next = first + MEGAPAGE_PAGES;
+                    let page = Page::of_addr(VirtAddr::new(first * PAGE_SIZE));
+                    return Some((page, frame, flags, PageSize::Size4MiB));
+                }
+                TranslateResult::Mapped { frame, flags, size: PageSize::Size4KiB, .. } => {
+                    self.next += 1;
+                    return Some((Page::of_addr(addr), frame, flags, PageSize::Size4KiB));
+                }
+                TranslateResult::NotMapped | TranslateResult::InvalidEntry => {
+                    if self.table.region_mapped(addr) {
+                        self.next += 1;
+                    } else {
+                        self.next = (self.next & !(MEGAPAGE_PAGES - 1)) + MEGAPAGE_PAGES;
+                    }
+                }
+            }
+        }
+        None
+    }
+}
+
+/// The combined flags of all pages in a range, as returned by `range_flags_summary`.
+///
+/// Unmapped pages count as having no flags set. For an empty range, `all` contains every flag.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct FlagsSummary {
+    /// The flags set on every page in the range.
+    pub all: PageTableFlags,
+    /// The flags set on at least one page in the range.
+    pub any: PageTableFlags,
+    /// The number of mapped pages.
+    pub mapped: usize,
+    /// The number of pages in the range.
+    pub pages: usize,
+}
+
+impl FlagsSummary {
+    pub(crate) fn new() -> Self {
+        FlagsSummary { all: PageTableFlags::all(), any: PageTableFlags::empty(), mapped: 0, pages: 0 }
+    }
+
+    /// Adds one page with the flags of its leaf entry, or an invalid entry if it is unmapped.
+    pub(crate) fn add(&mut self, flags: PageTableFlags) {
+        self.pages += 1;
+        if flags.contains(PageTableFlags::VALID) {
+            self.mapped += 1;
+            self.any |= flags;
+            self.all &= flags;
+        } else {
+            self.all = PageTableFlags::empty();
+        }
+    }
+
+    /// Returns whether every page in the range is mapped.
+    pub fn all_mapped(&self) -> bool {
+        self.mapped == self.pages
+    }
+
+    /// Returns whether every page in the range is mapped with all of `flags`, e.g.
+    /// `USER | READABLE` to check a user buffer passed to a syscall.
+    pub fn all_contain(&self, flags: PageTableFlags) -> bool {
+        self.all.contains(flags | PageTableFlags::VALID)
+    }
+
+    /// Returns whether any page in the range is mapped with one of `flags`.
+    pub fn any_intersect(&self, flags: PageTableFlags) -> bool {
+        self.any.intersects(flags)
+    }
+}
+
+/// Counts of the leaves and tables of a page table, as returned by `RecursivePageTable::stats`.
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub struct PageTableStats {
+    /// The number of valid 4K leaf entries.
+    pub pages_4k: usize,
+    /// The number of valid 4M leaf entries in the p2 table.
+    pub pages_4m: usize,
+    /// The number of p1 tables.
+    pub p1_tables: usize,
+}
+
+impl PageTableStats {
+    /// Returns the memory used by the p1 tables and the p2 table in bytes.
+    pub fn table_bytes(&self) -> usize {
+        (self.p1_tables + 1) * PAGE_SIZE
+    }
+
+    /// Adds the p1 table at `p2_index` and its valid entries mapping pages in `pages`.
+    pub(crate) fn add_p1(&mut self, p2_index: usize, p1: &PageTable, pages: Option<PageRange>) {
+        self.p1_tables += 1;
+        for i in 0..ENTRY_COUNT {
+            let in_range = pages.map_or(true, |pages| pages.contains(Page::from_page_table_indices(p2_index, i)));
+            if in_range && p1[i].flags().contains(PageTableFlags::VALID) {
+                self.pages_4k += 1;
+            }
+        }
+    }
+}
@@ -0,0 +1,76 @@
+use super::cow::*;
+use super::frame_alloc::*;
+use super::page_table::*;
+use super::phys::*;
+use super::recursive::*;
+use addr::*;
+
+#[repr(align(4096))]
+struct ZeroPage([u8; PAGE_SIZE]);
+
+static ZERO_PAGE: ZeroPage = ZeroPage([0; PAGE_SIZE]);
+
+/// Returns the frame of the zero page shared by all `map_zero_page` mappings.
+///
+/// The zero page is part of the crate's read-only data, so `table` has to map the kernel image.
+/// Returns `None` if it doesn't.
+pub fn zero_frame<T: Translate>(table: &T) -> Option<Frame> {
+    match table.translate(VirtAddr::new(ZERO_PAGE.0.as_ptr() as usize)) {
+        TranslateResult::Mapped { frame, offset, .. } => {
+            Some(Frame::of_addr(PhysAddr::new(frame.start_address().as_u32() + offset as u32)))
+        }
+        _ => None,
+    }
+}
+
+/// Maps `page` read-only to the shared zero page.
+///
+/// `flags` are the flags of the mapping once it is written to and must contain `READABLE` and
+/// `WRITABLE`, otherwise `InvalidLeafFlags` is returned; `WRITABLE` is dropped until then.
+/// Returns `ZeroPageNotMapped` if `mapper` doesn't map the zero page itself.
+/// The first write faults, and `resolve_zero_fault` replaces the zero page by a private zeroed
+/// frame, so untouched anonymous memory costs no frames.
+pub fn map_zero_page<M, A>(mapper: &mut M, page: Page, flags: PageTableFlags, allocator: &mut A) -> Result<MapperFlush, MapToError>
+    where M: Mapper + Translate, A: FrameAllocator,
+{
+    if !flags.contains(PageTableFlags::READABLE | PageTableFlags::WRITABLE) {
+        return Err(MapToError::InvalidLeafFlags);
+    }
+    let frame = zero_frame(mapper).ok_or(MapToError::ZeroPageNotMapped)?;
+    mapper.map_to(page, frame, flags - PageTableFlags::WRITABLE, allocator)
+}
+
+/// Returns whether `page` maps the shared zero page, i.e. whether a write fault on it should
+/// be handled by `resolve_zero_fault`.
+pub fn is_zero_page<T: Translate>(table: &T, page: Page) -> bool {
+    match table.translate(page.start_address()) {
+        TranslateResult::Mapped { frame, size: PageSize::Size4KiB, .. } => Some(frame) == zero_frame(table),
+        _ => false,
+    }
+}
+
+/// Resolves a write fault on a zero page mapping.
+///
+/// Allocates a frame, zeroes it through `phys` and maps it writable at `page`. Returns
+/// `NotCopyOnWrite` if `page` doesn't map the zero page or isn't readable.
+///
+/// Only `map_zero_page`, which requires writable flags, should map the zero page, so every
+/// zero page mapping is meant to become writable. Read-only zeroed memory must use a frame
+/// of its own.
+pub fn resolve_zero_fault<M, P, A>(mapper: &mut M, page: Page, phys: &mut P, allocator: &mut A) -> Result<MapperFlush, CowError>
+    where M: Mapper + Translate, P: PhysWrite, A: FrameAllocator,
+{
+    let flags = match mapper.translate(page.start_address()) {
+        TranslateResult::Mapped { flags, size: PageSize::Size4KiB, .. } => flags,
+        _ => return Err(CowError::PageNotMapped),
+    };
+    if !is_zero_page(mapper, page) || !flags.contains(PageTableFlags::READABLE) {
+        return Err(CowError::NotCopyOnWrite);
+    }
+    let frame = allocator.alloc().ok_or(CowError::FrameAllocationFailed)?;
+    phys.clear_frame(frame);
+    mapper.unmap(page).expect("page vanished").1.ignore();
+    let flush = mapper.map_to(page, frame, flags | PageTableFlags::WRITABLE, allocator)
+        .expect("p1 table vanished");
+    Ok(flush)
+}
@@ -69,6 +69,23 @@ macro_rules! write_csr_as_usize {
     };
 }
 
+macro_rules! write_csr_as_usize_unsafe {
+    ($csr_number:expr) => {
+        write_csr!($csr_number);
+
+        /// Writes the CSR
+        ///
+        /// # Safety
+        ///
+        /// The new value changes state that safe code relies on, e.g. which privilege mode
+        /// traps go to or where the shadow stack lives.
+        #[inline(always)]
+        pub unsafe fn write(bits: usize) {
+            _write(bits)
+        }
+    };
+}
+
 macro_rules! set {
     ($csr_number:expr) => {
         /// Set the CSR
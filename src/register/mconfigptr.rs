@@ -0,0 +1,39 @@
+//! mconfigptr register
+
+use core::num::NonZeroUsize;
+
+/// mconfigptr register
+#[derive(Clone, Copy, Debug)]
+pub struct Mconfigptr {
+    bits: NonZeroUsize,
+}
+
+impl Mconfigptr {
+    /// Returns the contents of the register as raw bits
+    pub fn bits(&self) -> usize {
+        self.bits.get()
+    }
+
+    /// Returns the physical address of the configuration data structure
+    pub fn address(&self) -> usize {
+        self.bits()
+    }
+}
+
+/// Reads the CSR
+#[inline]
+pub fn read() -> Option<Mconfigptr> {
+    match () {
+        #[cfg(any(target_arch = "riscv32", target_arch = "riscv64"))]
+        () => {
+            let r: usize;
+            unsafe {
+                asm!("csrrs $0, 0xF15, x0" : "=r"(r) ::: "volatile");
+            }
+            // A zero mconfigptr means that there is no configuration data structure.
+            NonZeroUsize::new(r).map(|bits| Mconfigptr { bits })
+        }
+        #[cfg(not(any(target_arch = "riscv32", target_arch = "riscv64")))]
+        () => unimplemented!(),
+    }
+}
@@ -0,0 +1,4 @@
+//! medeleg register
+
+read_csr_as_usize!(0x302);
+write_csr_as_usize_unsafe!(0x302);
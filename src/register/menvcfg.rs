@@ -0,0 +1,36 @@
+//! menvcfg register
+
+/// menvcfg register
+#[derive(Clone, Copy, Debug)]
+pub struct Menvcfg {
+    bits: usize,
+}
+
+impl Menvcfg {
+    /// Returns the contents of the register as raw bits
+    #[inline(always)]
+    pub fn bits(&self) -> usize {
+        self.bits
+    }
+
+    /// Landing Pad Enable (Zicfilp) for S-mode
+    #[inline(always)]
+    pub fn lpe(&self) -> bool {
+        self.bits & (1 << 2) == 1 << 2
+    }
+
+    /// Shadow Stack Enable (Zicfiss) for S-mode
+    #[inline(always)]
+    pub fn sse(&self) -> bool {
+        self.bits & (1 << 3) == 1 << 3
+    }
+}
+
+read_csr_as!(Menvcfg, 0x30a);
+set!(0x30a);
+clear!(0x30a);
+
+/// Landing Pad Enable
+set_clear_csr!(set_lpe, clear_lpe, 1 << 2);
+/// Shadow Stack Enable
+set_clear_csr!(set_sse, clear_sse, 1 << 3);
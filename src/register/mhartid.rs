@@ -0,0 +1,3 @@
+//! mhartid register
+
+read_csr_as_usize!(0xf14);
@@ -0,0 +1,4 @@
+//! mideleg register
+
+read_csr_as_usize!(0x303);
+write_csr_as_usize_unsafe!(0x303);
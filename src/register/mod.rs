@@ -14,9 +14,15 @@
 mod macros;
 
 pub mod mcause;
+pub mod mconfigptr;
 pub mod mcycle;
 pub mod mcycleh;
+pub mod medeleg;
+#[cfg(feature = "cfi")]
+pub mod menvcfg;
 pub mod mepc;
+pub mod mhartid;
+pub mod mideleg;
 pub mod mie;
 pub mod mip;
 pub mod minstret;
@@ -36,6 +42,13 @@ pub mod stval;
 pub mod satp;
 pub mod sscratch;
 pub mod sepc;
+#[cfg(feature = "cfi")]
+pub mod senvcfg;
+#[cfg(feature = "cfi")]
+pub mod ssp;
 
 pub mod time;
 pub mod timeh;
+
+mod snapshot;
+pub use self::snapshot::*;
@@ -10,6 +10,17 @@ pub struct Satp {
 }
 
 impl Satp {
+    /// Creates a satp value from the translation mode, address space identifier and the
+    /// physical page number of the root page table.
+    #[inline(always)]
+    pub fn new(mode: Mode, asid: usize, ppn: usize) -> Satp {
+        let mut satp = Satp { bits: 0 };
+        satp.set_mode(mode);
+        satp.set_asid(asid);
+        satp.set_ppn(ppn);
+        satp
+    }
+
     /// Returns the contents of the register as raw bits
     #[inline(always)]
     pub fn bits(&self) -> usize {
@@ -74,6 +85,48 @@ impl Satp {
     pub fn frame(&self) -> Frame {
         Frame::of_addr(PhysAddr::new((self.ppn() as u32) << 12))
     }
+
+    #[cfg(target_pointer_width = "32")]
+    #[inline(always)]
+    pub fn set_mode(&mut self, mode: Mode) {
+        self.bits.set_bits(31..32, mode as usize);
+    }
+
+    #[cfg(target_pointer_width = "64")]
+    #[inline(always)]
+    pub fn set_mode(&mut self, mode: Mode) {
+        self.bits.set_bits(60..64, mode as usize);
+    }
+
+    #[cfg(target_pointer_width = "32")]
+    #[inline(always)]
+    pub fn set_asid(&mut self, asid: usize) {
+        self.bits.set_bits(22..31, asid);
+    }
+
+    #[cfg(target_pointer_width = "64")]
+    #[inline(always)]
+    pub fn set_asid(&mut self, asid: usize) {
+        self.bits.set_bits(44..60, asid);
+    }
+
+    #[cfg(target_pointer_width = "32")]
+    #[inline(always)]
+    pub fn set_ppn(&mut self, ppn: usize) {
+        self.bits.set_bits(0..22, ppn);
+    }
+
+    #[cfg(target_pointer_width = "64")]
+    #[inline(always)]
+    pub fn set_ppn(&mut self, ppn: usize) {
+        self.bits.set_bits(0..44, ppn);
+    }
+
+    #[cfg(target_pointer_width = "32")]
+    #[inline(always)]
+    pub fn set_frame(&mut self, frame: Frame) {
+        self.set_ppn(frame.number());
+    }
 }
 
 #[cfg(target_pointer_width = "32")]
@@ -92,9 +145,55 @@ write_csr!(0x180);
 #[inline(always)]
 #[cfg(target_pointer_width = "32")]
 pub unsafe fn set(mode: Mode, asid: usize, frame: Frame) {
-    let mut bits = 0usize;
-    bits.set_bits(31..32, mode as usize);
-    bits.set_bits(22..31, asid);
-    bits.set_bits(0..22, frame.number());
-    _write(bits);
-}
\ No newline at end of file
+    write(Satp::new(mode, asid, frame.number()));
+}
+
+/// Writes the CSR
+#[inline(always)]
+pub unsafe fn write(satp: Satp) {
+    _write(satp.bits);
+}
+
+/// Restores the satp value that was active before `switch` when dropped.
+///
+/// The drop runs on unwinding too, so a panic inside a temporarily activated address space
+/// doesn't leave the hart on the wrong page table.
+#[must_use = "the previous satp is restored as soon as the guard is dropped"]
+pub struct SatpGuard {
+    previous: Satp,
+    asid: usize,
+}
+
+impl Drop for SatpGuard {
+    fn drop(&mut self) {
+        unsafe { write(self.previous); }
+        // The temporary ASID may be reused, and may even equal the restored one.
+        ::asm::sfence_vma_asid(self.asid);
+    }
+}
+
+/// Writes `satp` and flushes the TLB entries of its ASID. The previous value is restored when
+/// the returned guard is dropped.
+///
+/// # Safety
+///
+/// The code, stack and data in use must be mapped at the same addresses in the new address
+/// space, for as long as the guard lives.
+pub unsafe fn switch(satp: Satp) -> SatpGuard {
+    let previous = read();
+    write(satp);
+    ::asm::sfence_vma_asid(satp.asid());
+    SatpGuard { previous, asid: satp.asid() }
+}
+
+/// Runs `f` with the Sv32 page table in `root` active under `asid`, then switches back to the
+/// previous page table, even if `f` panics.
+///
+/// # Safety
+///
+/// See `switch`.
+#[cfg(target_pointer_width = "32")]
+pub unsafe fn with_page_table<F, T>(root: Frame, asid: usize, f: F) -> T where F: FnOnce() -> T {
+    let _guard = switch(Satp::new(Mode::Sv32, asid, root.number()));
+    f()
+}
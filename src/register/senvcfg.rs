@@ -0,0 +1,36 @@
+//! senvcfg register
+
+/// senvcfg register
+#[derive(Clone, Copy, Debug)]
+pub struct Senvcfg {
+    bits: usize,
+}
+
+impl Senvcfg {
+    /// Returns the contents of the register as raw bits
+    #[inline(always)]
+    pub fn bits(&self) -> usize {
+        self.bits
+    }
+
+    /// Landing Pad Enable (Zicfilp) for U-mode
+    #[inline(always)]
+    pub fn lpe(&self) -> bool {
+        self.bits & (1 << 2) == 1 << 2
+    }
+
+    /// Shadow Stack Enable (Zicfiss) for U-mode
+    #[inline(always)]
+    pub fn sse(&self) -> bool {
+        self.bits & (1 << 3) == 1 << 3
+    }
+}
+
+read_csr_as!(Senvcfg, 0x10a);
+set!(0x10a);
+clear!(0x10a);
+
+/// Landing Pad Enable
+set_clear_csr!(set_lpe, clear_lpe, 1 << 2);
+/// Shadow Stack Enable
+set_clear_csr!(set_sse, clear_sse, 1 << 3);
@@ -51,9 +51,16 @@ impl Sie {
 }
 
 read_csr_as!(Sie, 0x104);
+write_csr!(0x104);
 set!(0x104);
 clear!(0x104);
 
+/// Writes the CSR
+#[inline(always)]
+pub unsafe fn write(sie: Sie) {
+    _write(sie.bits);
+}
+
 /// User Software Interrupt Enable
 set_clear_csr!(set_usoft, clear_usoft, 1 << 0);
 /// Supervisor Software Interrupt Enable
@@ -0,0 +1,194 @@
+//! Snapshots of the supervisor CSRs
+//!
+//! Capture a `Snapshot` before and after e.g. a context switch or an SBI call and use `diff` to
+//! list the CSR fields that changed. Field layouts are those of RV32.
+
+use core::fmt;
+use super::{satp, scause, sepc, sie, sip, sscratch, sstatus, stval, stvec};
+
+/// The raw values of the supervisor CSRs at one point in time.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Snapshot {
+    pub sstatus: usize,
+    pub sie: usize,
+    pub sip: usize,
+    pub stvec: usize,
+    pub sscratch: usize,
+    pub sepc: usize,
+    pub scause: usize,
+    pub stval: usize,
+    pub satp: usize,
+}
+
+impl Snapshot {
+    /// Reads all supervisor CSRs.
+    pub fn capture() -> Self {
+        Snapshot {
+            sstatus: sstatus::read().bits(),
+            sie: sie::read().bits(),
+            sip: sip::read().bits(),
+            stvec: stvec::read().bits(),
+            sscratch: sscratch::read(),
+            sepc: sepc::read(),
+            scause: scause::read().bits(),
+            stval: stval::read(),
+            satp: satp::read().bits(),
+        }
+    }
+
+    fn get(&self, csr: Csr) -> usize {
+        match csr {
+            Csr::Sstatus => self.sstatus,
+            Csr::Sie => self.sie,
+            Csr::Sip => self.sip,
+            Csr::Stvec => self.stvec,
+            Csr::Sscratch => self.sscratch,
+            Csr::Sepc => self.sepc,
+            Csr::Scause => self.scause,
+            Csr::Stval => self.stval,
+            Csr::Satp => self.satp,
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+enum Csr { Sstatus, Sie, Sip, Stvec, Sscratch, Sepc, Scause, Stval, Satp }
+
+/// A CSR field: register, register name, field name, lowest bit and width.
+struct Field(Csr, &'static str, &'static str, usize, usize);
+
+impl Field {
+    fn extract(&self, bits: usize) -> usize {
+        let Field(_, _, _, lo, width) = *self;
+        let value = bits >> lo;
+        if width >= 32 { value } else { value & ((1 << width) - 1) }
+    }
+}
+
+static FIELDS: &[Field] = &[
+    Field(Csr::Sstatus, "sstatus", "UIE", 0, 1),
+    Field(Csr::Sstatus, "sstatus", "SIE", 1, 1),
+    Field(Csr::Sstatus, "sstatus", "UPIE", 4, 1),
+    Field(Csr::Sstatus, "sstatus", "SPIE", 5, 1),
+    Field(Csr::Sstatus, "sstatus", "SPP", 8, 1),
+    Field(Csr::Sstatus, "sstatus", "FS", 13, 2),
+    Field(Csr::Sstatus, "sstatus", "XS", 15, 2),
+    Field(Csr::Sstatus, "sstatus", "SUM", 18, 1),
+    Field(Csr::Sstatus, "sstatus", "MXR", 19, 1),
+    Field(Csr::Sstatus, "sstatus", "SD", 31, 1),
+    Field(Csr::Sie, "sie", "USIE", 0, 1),
+    Field(Csr::Sie, "sie", "SSIE", 1, 1),
+    Field(Csr::Sie, "sie", "UTIE", 4, 1),
+    Field(Csr::Sie, "sie", "STIE", 5, 1),
+    Field(Csr::Sie, "sie", "UEIE", 8, 1),
+    Field(Csr::Sie, "sie", "SEIE", 9, 1),
+    Field(Csr::Sip, "sip", "USIP", 0, 1),
+    Field(Csr::Sip, "sip", "SSIP", 1, 1),
+    Field(Csr::Sip, "sip", "UTIP", 4, 1),
+    Field(Csr::Sip, "sip", "STIP", 5, 1),
+    Field(Csr::Sip, "sip", "UEIP", 8, 1),
+    Field(Csr::Sip, "sip", "SEIP", 9, 1),
+    Field(Csr::Stvec, "stvec", "MODE", 0, 2),
+    Field(Csr::Stvec, "stvec", "BASE", 2, 30),
+    Field(Csr::Sscratch, "sscratch", "", 0, 32),
+    Field(Csr::Sepc, "sepc", "", 0, 32),
+    Field(Csr::Scause, "scause", "CODE", 0, 31),
+    Field(Csr::Scause, "scause", "INTERRUPT", 31, 1),
+    Field(Csr::Stval, "stval", "", 0, 32),
+    Field(Csr::Satp, "satp", "PPN", 0, 22),
+    Field(Csr::Satp, "satp", "ASID", 22, 9),
+    Field(Csr::Satp, "satp", "MODE", 31, 1),
+];
+
+/// A CSR field whose value differs between two snapshots.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Change {
+    /// Name of the CSR, e.g. `"sstatus"`.
+    pub csr: &'static str,
+    /// Name of the field, e.g. `"SIE"`, or `""` if the whole register is one field.
+    pub field: &'static str,
+    pub before: usize,
+    pub after: usize,
+}
+
+impl fmt::Display for Change {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.field.is_empty() {
+            write!(f, "{}: {:#x} -> {:#x}", self.csr, self.before, self.after)
+        } else {
+            write!(f, "{}.{}: {:#x} -> {:#x}", self.csr, self.field, self.before, self.after)
+        }
+    }
+}
+
+/// An iterator over the changed fields between two snapshots, returned by `diff`.
+pub struct Diff {
+    before: Snapshot,
+    after: Snapshot,
+    index: usize,
+}
+
+impl Iterator for Diff {
+    type Item = Change;
+
+    fn next(&mut self) -> Option<Change> {
+        while let Some(field) = FIELDS.get(self.index) {
+            self.index += 1;
+            let Field(csr, csr_name, field_name, _, _) = *field;
+            let before = field.extract(self.before.get(csr));
+            let after = field.extract(self.after.get(csr));
+            if before != after {
+                return Some(Change { csr: csr_name, field: field_name, before, after });
+            }
+        }
+        None
+    }
+}
+
+/// Returns the CSR fields that differ between `before` and `after`.
+pub fn diff(before: Snapshot, after: Snapshot) -> Diff {
+    Diff { before, after, index: 0 }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn snapshot() -> Snapshot {
+        Snapshot {
+            sstatus: 1 << 1 | 1 << 5,
+            sie: 1 << 5,
+            sip: 0,
+            stvec: 0x8020_0000,
+            sscratch: 0,
+            sepc: 0x8020_1000,
+            scause: 8,
+            stval: 0,
+            satp: 1 << 31 | 0x80400,
+        }
+    }
+
+    #[test]
+    fn diff_of_equal_snapshots_is_empty() {
+        assert_eq!(diff(snapshot(), snapshot()).next(), None);
+    }
+
+    #[test]
+    fn diff_reports_changed_fields() {
+        let before = snapshot();
+        let after = Snapshot {
+            sstatus: before.sstatus & !(1 << 1) | 1 << 8,
+            stvec: before.stvec | 1,
+            sepc: before.sepc + 4,
+            satp: before.satp | 3 << 22,
+            ..before
+        };
+        let mut changes = diff(before, after);
+        assert_eq!(changes.next(), Some(Change { csr: "sstatus", field: "SIE", before: 1, after: 0 }));
+        assert_eq!(changes.next(), Some(Change { csr: "sstatus", field: "SPP", before: 0, after: 1 }));
+        assert_eq!(changes.next(), Some(Change { csr: "stvec", field: "MODE", before: 0, after: 1 }));
+        assert_eq!(changes.next(), Some(Change { csr: "sepc", field: "", before: 0x8020_1000, after: 0x8020_1004 }));
+        assert_eq!(changes.next(), Some(Change { csr: "satp", field: "ASID", before: 0, after: 3 }));
+        assert_eq!(changes.next(), None);
+    }
+}
@@ -0,0 +1,4 @@
+//! ssp register (Zicfiss shadow stack pointer)
+
+read_csr_as_usize!(0x011);
+write_csr_as_usize_unsafe!(0x011);
@@ -17,6 +17,12 @@ pub enum SPP {
 }
 
 impl Sstatus {
+    /// Returns the contents of the register as raw bits
+    #[inline(always)]
+    pub fn bits(&self) -> usize {
+        self.bits
+    }
+
     /// User Interrupt Enable
     #[inline(always)]
     pub fn uie(&self) -> bool {
@@ -94,9 +100,16 @@ impl Sstatus {
 }
 
 read_csr_as!(Sstatus, 0x100);
+write_csr!(0x100);
 set!(0x100);
 clear!(0x100);
 
+/// Writes the CSR
+#[inline(always)]
+pub unsafe fn write(sstatus: Sstatus) {
+    _write(sstatus.bits);
+}
+
 /// User Interrupt Enable
 set_clear_csr!(set_uie, clear_uie, 1 << 0);
 /// Supervisor Interrupt Enable
@@ -111,6 +124,42 @@ set_csr!(set_xpie, 1 << 5);
 set_clear_csr!(set_mxr, clear_mxr, 1 << 19);
 /// Permit Supervisor User Memory access
 set_clear_csr!(set_sum, clear_sum, 1 << 18);
+
+/// Execute closure `f` with MXR set, so loads from execute-only pages succeed.
+///
+/// This is how a trap handler reads the faulting instruction for emulation when the code is
+/// mapped execute-only. MXR is cleared again afterwards, unless it was set before.
+pub fn with_mxr<F, R>(f: F) -> R where F: FnOnce() -> R {
+    let sstatus = read();
+    unsafe { set_mxr(); }
+    let r = f();
+    if !sstatus.mxr() {
+        unsafe { clear_mxr(); }
+    }
+    r
+}
+
+/// Execute closure `f` with SUM set, so S-mode loads and stores to user pages succeed.
+///
+/// SUM is cleared again afterwards, unless it was set before.
+pub fn with_sum<F, R>(f: F) -> R where F: FnOnce() -> R {
+    let sstatus = read();
+    unsafe { set_sum(); }
+    let r = f();
+    if !sstatus.sum() {
+        unsafe { clear_sum(); }
+    }
+    r
+}
+
+/// Reads `*ptr` with MXR set, see `with_mxr`.
+///
+/// # Safety
+///
+/// `ptr` must be valid for reads once execute-only pages are readable.
+pub unsafe fn read_executable<T: Copy>(ptr: *const T) -> T {
+    with_mxr(|| ::core::ptr::read_volatile(ptr))
+}
 /// Supervisor Previous Privilege Mode
 #[inline(always)]
 pub unsafe fn set_spp(spp: SPP) {